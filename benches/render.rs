@@ -0,0 +1,95 @@
+//! Compares drawing the framebuffer as one `fill_rectangle` per lit pixel, as the canvas used to,
+//! with drawing it as a single nearest-filtered RGBA image. Both are rasterised by the software
+//! renderer into a window-sized pixmap.
+
+use chip8::machine::ColourFrame;
+use chip8::palette::Palette;
+use chip8::rotation::Rotation;
+use chip8::ui;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use iced_tiny_skia::core::image::FilterMethod;
+use iced_tiny_skia::core::Color;
+use iced_tiny_skia::core::Point;
+use iced_tiny_skia::core::Rectangle;
+use iced_tiny_skia::core::Size;
+use iced_tiny_skia::geometry::Frame;
+use iced_tiny_skia::graphics::geometry::Path;
+use iced_tiny_skia::graphics::Viewport;
+use iced_tiny_skia::Backend;
+use iced_tiny_skia::Primitive;
+
+/// Display sizes and the scale each is drawn at: the standard display, SUPER-CHIP hi-res and MegaChip.
+static SIZES: [(usize, usize, usize); 3] = [(64, 32, 10), (128, 64, 5), (256, 192, 4)];
+
+/// Every other pixel lit, with a little phosphor fade on some of them.
+fn intensity(width: usize, height: usize) -> Vec<f32> {
+    (0..width * height)
+        .map(|pixel| if (pixel + pixel / width).is_multiple_of(2) { 1. } else { (pixel % 3) as f32 * 0.25 })
+        .collect()
+}
+
+/// The old canvas: a black background and one rectangle per lit pixel.
+fn rectangles(intensity: &[f32], width: usize, window: Size) -> Primitive {
+    let mut frame = Frame::new(window);
+    let point_size = Size {
+        width: window.width / width as f32,
+        height: window.height / (intensity.len() / width) as f32,
+    };
+    frame.fill(&Path::rectangle(Point::ORIGIN, window), Color::BLACK);
+    for (pixel, intensity) in intensity.iter().enumerate() {
+        if *intensity > 0. {
+            let point = Point {
+                x: point_size.width * (pixel % width) as f32,
+                y: point_size.height * (pixel / width) as f32,
+            };
+            frame.fill_rectangle(point, point_size, Color::from_rgb(*intensity, *intensity, *intensity));
+        }
+    }
+    frame.into_primitive()
+}
+
+/// The current canvas: the framebuffer converted to one image and scaled up.
+fn image(intensity: &[f32], width: usize, window: Size) -> Primitive {
+    let handle = if width <= 128 {
+        ui::framebuffer_image(intensity, width, Palette::Classic, Rotation::None)
+    } else {
+        let frame = ColourFrame {
+            width,
+            height: intensity.len() / width,
+            pixels: intensity.iter().map(|intensity| Palette::Classic.blend(*intensity)).collect(),
+        };
+        ui::colour_image(&frame, Rotation::None)
+    };
+    Primitive::Image {
+        handle,
+        filter_method: FilterMethod::Nearest,
+        bounds: Rectangle::new(Point::ORIGIN, window),
+    }
+}
+
+fn render(c: &mut Criterion) {
+    let mut backend = Backend::new();
+    let mut group = c.benchmark_group("render");
+    for (width, height, scale) in SIZES {
+        let window = Size::new((width * scale) as u32, (height * scale) as u32);
+        let bounds = Size::new(window.width as f32, window.height as f32);
+        let viewport = Viewport::with_physical_size(window, 1.);
+        let mut pixmap = tiny_skia::Pixmap::new(window.width, window.height).unwrap();
+        let mut clip_mask = tiny_skia::Mask::new(window.width, window.height).unwrap();
+        let intensity = intensity(width, height);
+        let size = format!("{}x{}", width, height);
+        let mut draw = |primitive: Primitive| {
+            let damage = [Rectangle::with_size(bounds)];
+            backend.draw(&mut pixmap.as_mut(), &mut clip_mask, &[primitive], &viewport, &damage, Color::BLACK, &[] as &[&str]);
+        };
+        group.bench_function(BenchmarkId::new("fill_rectangle", &size), |b| b.iter(|| draw(rectangles(&intensity, width, bounds))));
+        group.bench_function(BenchmarkId::new("image", &size), |b| b.iter(|| draw(image(&intensity, width, bounds))));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
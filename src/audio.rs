@@ -0,0 +1,47 @@
+//! Sound output for the window, built with the `audio` feature.
+
+use crate::megachip::SAMPLE_RATE;
+use rodio::buffer::SamplesBuffer;
+use rodio::OutputStream;
+use rodio::Sink;
+use std::fmt;
+
+/// Frames of sound queued before more are dropped, so fast-forward doesn't build up a delay.
+static MAX_QUEUED_FRAMES: usize = 4;
+
+/// Plays each frame's sound on the default output device.
+pub struct Speaker {
+    // Playback stops when the stream is dropped
+    _stream: OutputStream,
+    sink: Sink,
+}
+
+impl fmt::Debug for Speaker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Speaker").field("queued_frames", &self.sink.len()).finish()
+    }
+}
+
+impl Speaker {
+    /// Opens the default output device, or returns `None` with a warning if there isn't one.
+    pub fn open() -> Option<Speaker> {
+        let opened = OutputStream::try_default().map_err(|error| error.to_string()).and_then(|(stream, handle)| {
+            let sink = Sink::try_new(&handle).map_err(|error| error.to_string())?;
+            Ok(Speaker { _stream: stream, sink })
+        });
+        match opened {
+            Ok(speaker) => Some(speaker),
+            Err(error) => {
+                eprintln!("Warning: no sound: {}", error);
+                None
+            }
+        }
+    }
+
+    /// Queues one frame's samples, at `megachip::SAMPLE_RATE`.
+    pub fn play(&self, samples: &[f32]) {
+        if !samples.is_empty() && self.sink.len() < MAX_QUEUED_FRAMES {
+            self.sink.append(SamplesBuffer::new(1, SAMPLE_RATE, samples.to_vec()));
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use crate::machine::ColourFrame;
+use crate::palette::Palette;
+use crate::rotation::Rotation;
+use crate::state::pixel_bitmask;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::path::Path;
+
+#[derive(Debug)]
+pub enum CaptureError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    Gif(gif::EncodingError),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CaptureError::Io(error) => write!(f, "could not write capture: {}", error),
+            CaptureError::Png(error) => write!(f, "could not encode PNG: {}", error),
+            CaptureError::Gif(error) => write!(f, "could not encode GIF: {}", error),
+        }
+    }
+}
+
+impl From<io::Error> for CaptureError {
+    fn from(error: io::Error) -> Self {
+        CaptureError::Io(error)
+    }
+}
+
+impl From<png::EncodingError> for CaptureError {
+    fn from(error: png::EncodingError) -> Self {
+        CaptureError::Png(error)
+    }
+}
+
+impl From<gif::EncodingError> for CaptureError {
+    fn from(error: gif::EncodingError) -> Self {
+        CaptureError::Gif(error)
+    }
+}
+
+/// Saves the framebuffer, `display_width` pixels across, as a PNG, each CHIP-8 pixel becoming a
+/// `scale` x `scale` square.
+pub fn save_screenshot(path: &Path, video: &[u128], display_width: usize, scale: u32, palette: Palette, rotation: Rotation) -> Result<(), CaptureError> {
+    let (width, height) = (display_width * scale as usize, video.len() * scale as usize);
+    let pixels: Vec<[u8; 3]> = stretch(&indices(video, display_width), display_width, video.len(), width, height)
+        .into_iter()
+        .map(|index| if index == 0 { palette.background() } else { palette.foreground() })
+        .collect();
+    let (rotated_width, rotated_height) = rotation.rotated_size(width, height);
+    write_png(path, rotated_width as u32, rotated_height as u32, &rotation.rotate(&pixels, width, height))
+}
+
+/// Saves a colour display as a PNG, each of its pixels becoming a `scale` x `scale` square.
+pub fn save_colour_screenshot(path: &Path, frame: &ColourFrame, scale: u32, rotation: Rotation) -> Result<(), CaptureError> {
+    let (width, height) = (frame.width * scale as usize, frame.height * scale as usize);
+    let pixels = stretch(&frame.pixels, frame.width, frame.height, width, height);
+    let (rotated_width, rotated_height) = rotation.rotated_size(width, height);
+    write_png(path, rotated_width as u32, rotated_height as u32, &rotation.rotate(&pixels, width, height))
+}
+
+fn write_png(path: &Path, width: u32, height: u32, pixels: &[[u8; 3]]) -> Result<(), CaptureError> {
+    let mut encoder = png::Encoder::new(BufWriter::new(File::create(path)?), width, height);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(pixels.as_flattened())?;
+    writer.finish()?;
+    Ok(())
+}
+
+/// Writes frames to an animated GIF until dropped.
+pub struct GifRecorder {
+    encoder: gif::Encoder<BufWriter<File>>,
+    /// Size of every frame before rotation.
+    width: usize,
+    height: usize,
+    rotation: Rotation,
+    frame_duration: f64,
+    elapsed: f64,
+    written_centiseconds: u64,
+}
+
+impl fmt::Debug for GifRecorder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GifRecorder")
+            .field("width", &self.width)
+            .field("height", &self.height)
+            .field("written_centiseconds", &self.written_centiseconds)
+            .finish()
+    }
+}
+
+impl GifRecorder {
+    /// Starts a recording of a display `display_size` low-res pixels across, as given by
+    /// `Platform::window_size`, each shown as a `scale` x `scale` square. Frames of another size,
+    /// such as MegaChip's colour display, are stretched to fit.
+    pub fn create(path: &Path, display_size: (usize, usize), scale: u32, palette: Palette, rotation: Rotation, frames_per_second: f64) -> Result<Self, CaptureError> {
+        let (width, height) = (display_size.0 * scale as usize, display_size.1 * scale as usize);
+        let (rotated_width, rotated_height) = rotation.rotated_size(width, height);
+        let global_palette: Vec<u8> = [palette.background(), palette.foreground()].concat();
+        let mut encoder = gif::Encoder::new(BufWriter::new(File::create(path)?), rotated_width as u16, rotated_height as u16, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(Self {
+            encoder,
+            width,
+            height,
+            rotation,
+            frame_duration: 100. / frames_per_second,
+            elapsed: 0.,
+            written_centiseconds: 0,
+        })
+    }
+
+    /// Appends one emulated frame, `display_width` pixels across, in the two colours of the palette.
+    pub fn push_frame(&mut self, video: &[u128], display_width: usize) -> Result<(), CaptureError> {
+        let pixels = stretch(&indices(video, display_width), display_width, video.len(), self.width, self.height);
+        let (width, height) = self.rotation.rotated_size(self.width, self.height);
+        let frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, self.rotation.rotate(&pixels, self.width, self.height), None);
+        self.write_frame(frame)
+    }
+
+    /// Appends one emulated frame shown in colour, with its own palette of up to 256 colours.
+    pub fn push_colour_frame(&mut self, frame: &ColourFrame) -> Result<(), CaptureError> {
+        let pixels = stretch(&frame.pixels, frame.width, frame.height, self.width, self.height);
+        let (width, height) = self.rotation.rotated_size(self.width, self.height);
+        let frame = gif::Frame::from_rgb_speed(width as u16, height as u16, self.rotation.rotate(&pixels, self.width, self.height).as_flattened(), 10);
+        self.write_frame(frame)
+    }
+
+    /// GIF delays are whole centiseconds, so delays are rounded against the running total to keep
+    /// the overall playback speed exact (2, 2, 1, ... at 60 fps).
+    fn write_frame(&mut self, mut frame: gif::Frame) -> Result<(), CaptureError> {
+        self.elapsed += self.frame_duration;
+        let delay = (self.elapsed.round() as u64 - self.written_centiseconds) as u16;
+        self.written_centiseconds += delay as u64;
+        frame.delay = delay;
+        self.encoder.write_frame(&frame)?;
+        Ok(())
+    }
+}
+
+/// One palette index per pixel of the framebuffer: 1 where a pixel is lit, otherwise 0.
+fn indices(video: &[u128], display_width: usize) -> Vec<u8> {
+    video
+        .iter()
+        .flat_map(|row| (0..display_width).map(move |col| if row & pixel_bitmask(col) != 0 { 1 } else { 0 }))
+        .collect()
+}
+
+/// Scales an image to `to_width` x `to_height` by repeating or dropping pixels.
+fn stretch<T: Copy>(pixels: &[T], width: usize, height: usize, to_width: usize, to_height: usize) -> Vec<T> {
+    let mut stretched = Vec::with_capacity(to_width * to_height);
+    for y in 0..to_height {
+        let row = &pixels[y * height / to_height * width..][..width];
+        stretched.extend((0..to_width).map(|x| row[x * width / to_width]));
+    }
+    stretched
+}
@@ -0,0 +1,211 @@
+//! Octo cartridges: animated GIFs that carry a program and its options alongside a label image.
+//!
+//! The payload is the UTF-8 JSON object `{"options": {...}, "program": "..."}` prefixed with its
+//! length as a 4-byte big-endian integer. It is split into nybbles, high nybble first, and stored in
+//! the low four bits of each pixel's palette index across as many 128x64 frames as needed. The high
+//! four bits select one of 16 label colours, so palette entry `i` is label colour `i >> 4` and the
+//! image still shows the label.
+//!
+//! Octo stores program source rather than a compiled ROM, and there is no Octo assembler here. Only
+//! cartridges whose program is nothing but byte literals, which is what `encode` writes, can be
+//! loaded; cartridges saved from Octo itself are refused with `RomError::UnsupportedFormat`.
+
+use crate::capture::CaptureError;
+use crate::init::RomError;
+use crate::octo::OctoOptions;
+use crate::palette::Palette;
+use crate::state::pixel_bitmask;
+use serde::Deserialize;
+use serde::Serialize;
+
+static WIDTH: u16 = 128;
+static HEIGHT: u16 = 64;
+static PIXELS_PER_FRAME: usize = WIDTH as usize * HEIGHT as usize;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Payload {
+    #[serde(default)]
+    options: OctoOptions,
+    program: String,
+}
+
+#[derive(Debug)]
+pub struct Cartridge {
+    pub rom: Vec<u8>,
+    pub options: OctoOptions,
+}
+
+/// Extracts the ROM and options from the bytes of a cartridge GIF.
+pub fn decode(gif_bytes: &[u8]) -> Result<Cartridge, RomError> {
+    let payload = read_payload(gif_bytes)?;
+    let rom = byte_literals(&payload.program).ok_or(RomError::UnsupportedFormat(
+        "only cartridges whose program is plain byte literals, such as the ones exported here, can be loaded; this one holds Octo source, which has to be assembled with Octo first",
+    ))?;
+    Ok(Cartridge { rom, options: payload.options })
+}
+
+fn read_payload(gif_bytes: &[u8]) -> Result<Payload, RomError> {
+    let invalid = |reason: &str| RomError::InvalidCartridge(reason.to_string());
+    let mut decode_options = gif::DecodeOptions::new();
+    decode_options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = decode_options.read_info(gif_bytes).map_err(|error| invalid(&error.to_string()))?;
+
+    let mut nybbles = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(|error| invalid(&error.to_string()))? {
+        nybbles.extend(frame.buffer.iter().map(|index| index & 0x0F));
+    }
+    let data: Vec<u8> = nybbles.chunks_exact(2).map(|pair| (pair[0] << 4) | pair[1]).collect();
+    if data.len() < 4 {
+        return Err(invalid("no payload"));
+    }
+    let size = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    let json = data.get(4..4 + size).ok_or_else(|| invalid("payload is truncated"))?;
+    serde_json::from_slice(json).map_err(|error| invalid(&error.to_string()))
+}
+
+/// Builds a cartridge GIF holding `rom` and `options`, labelled with `label`, `label_width` pixels
+/// across, stretched over the whole image.
+pub fn encode(rom: &[u8], options: &OctoOptions, label: &[u128], label_width: usize, palette: Palette) -> Result<Vec<u8>, CaptureError> {
+    let payload = Payload {
+        options: options.clone(),
+        program: byte_literal_source(rom),
+    };
+    let json = serde_json::to_vec(&payload).expect("cartridge payload always serialises");
+    let mut data = (json.len() as u32).to_be_bytes().to_vec();
+    data.extend_from_slice(&json);
+    let nybbles: Vec<u8> = data.iter().flat_map(|byte| [byte >> 4, byte & 0x0F]).collect();
+
+    let mut label_colours = [[0; 3]; 16];
+    label_colours[0] = palette.background();
+    label_colours[1] = palette.foreground();
+    let global_palette: Vec<u8> = (0..256).flat_map(|index| label_colours[index >> 4]).collect();
+    let label_indices: Vec<u8> = (0..PIXELS_PER_FRAME)
+        .map(|pixel| {
+            let (x, y) = (pixel % WIDTH as usize, pixel / WIDTH as usize);
+            if label[y * label.len() / HEIGHT as usize] & pixel_bitmask(x * label_width / WIDTH as usize) != 0 {
+                1 << 4
+            } else {
+                0
+            }
+        })
+        .collect();
+
+    let mut gif_bytes = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut gif_bytes, WIDTH, HEIGHT, &global_palette)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        for frame_nybbles in nybbles.chunks(PIXELS_PER_FRAME) {
+            let mut indices = label_indices.clone();
+            for (index, nybble) in indices.iter_mut().zip(frame_nybbles) {
+                *index |= nybble;
+            }
+            let mut frame = gif::Frame::from_indexed_pixels(WIDTH, HEIGHT, indices, None);
+            frame.delay = 10;
+            encoder.write_frame(&frame)?;
+        }
+    }
+    Ok(gif_bytes)
+}
+
+/// Writes `rom` as Octo source made only of byte literals after `: main`, which Octo assembles
+/// back into the same bytes at the program start address.
+fn byte_literal_source(rom: &[u8]) -> String {
+    let mut source = String::from(": main\n");
+    for line in rom.chunks(16) {
+        let literals: Vec<String> = line.iter().map(|byte| format!("0x{:02X}", byte)).collect();
+        source.push_str(&literals.join(" "));
+        source.push('\n');
+    }
+    source
+}
+
+/// Reads source written by `byte_literal_source`: comments, an optional leading `: main` and
+/// numeric literals. Returns `None` for anything that needs a real assembler.
+fn byte_literals(source: &str) -> Option<Vec<u8>> {
+    let mut tokens = source.lines().flat_map(|line| line.split('#').next().unwrap_or_default().split_whitespace()).peekable();
+    if tokens.peek() == Some(&":") {
+        tokens.next();
+        if tokens.next() != Some("main") {
+            return None;
+        }
+    }
+    tokens.map(parse_byte_literal).collect()
+}
+
+fn parse_byte_literal(token: &str) -> Option<u8> {
+    let value = if let Some(hex) = token.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = token.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else {
+        token.parse::<i32>().ok()?
+    };
+    match value {
+        0..=255 => Some(value as u8),
+        -128..=-1 => Some(value as i8 as u8),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Both fixtures follow Octo's cartridge layout but were written by a separate GIF encoder, so
+    // they also check the LZW stream and frame handling against something other than `encode`.
+    static OCTO_SOURCE: &[u8] = include_bytes!("../tests/fixtures/octo-source.gif");
+    static OCTO_BYTES: &[u8] = include_bytes!("../tests/fixtures/octo-bytes.gif");
+
+    #[test]
+    fn encode_then_decode_returns_the_rom_and_options() {
+        let rom: Vec<u8> = (0..=255).cycle().take(3000).collect();
+        let mut options = OctoOptions {
+            tickrate: Some(30),
+            screen_rotation: Some(90),
+            ..OctoOptions::default()
+        };
+        options.set_palette(Palette::Amber);
+        let label = [0x8000_0000_0000_0001_0000_0000_0000_0000u128; 32];
+        let gif_bytes = encode(&rom, &options, &label, 64, Palette::Amber).unwrap();
+        let cartridge = decode(&gif_bytes).unwrap();
+        assert_eq!(cartridge.rom, rom);
+        assert_eq!(cartridge.options, options);
+    }
+
+    #[test]
+    fn reads_the_payload_of_an_octo_cartridge() {
+        let payload = read_payload(OCTO_SOURCE).unwrap();
+        assert!(payload.program.starts_with("####"));
+        assert!(payload.program.contains(": main\n\tx := 10 y := 5 dx := 1 dy := 1\n"));
+        assert_eq!(payload.options.tickrate, Some(20));
+        assert_eq!(payload.options.fill_color.as_deref(), Some("#FFCC00"));
+        assert_eq!(payload.options.load_store_quirks, Some(true));
+        assert_eq!(payload.options.max_size, Some(3584));
+    }
+
+    #[test]
+    fn refuses_octo_source() {
+        assert!(matches!(decode(OCTO_SOURCE), Err(RomError::UnsupportedFormat(_))));
+    }
+
+    #[test]
+    fn assembles_byte_literals_from_an_octo_cartridge() {
+        let cartridge = decode(OCTO_BYTES).unwrap();
+        assert_eq!(cartridge.rom, [0x00, 0xE0, 0xA2, 0x08, 0x60, 0x00, 0x61, 0x00, 0xD0, 0x15, 0x12, 0x06, 0xF0, 0x90, 0x90, 0x90, 0xF0]);
+        assert_eq!(cartridge.options.clip_quirks, Some(true));
+    }
+
+    #[test]
+    fn rejects_a_gif_without_a_payload() {
+        let gif_bytes = encode(&[], &OctoOptions::default(), &[0; 32], 64, Palette::Classic).unwrap();
+        let truncated = &gif_bytes[..gif_bytes.len() / 2];
+        assert!(matches!(decode(truncated), Err(RomError::InvalidCartridge(_))));
+    }
+
+    #[test]
+    fn byte_literals_accept_negative_and_binary_values() {
+        assert_eq!(byte_literals(": main -1 0b101 200 # comment\n0x7f"), Some(vec![0xFF, 0x05, 200, 0x7F]));
+        assert_eq!(byte_literals(": main v0 := 1"), None);
+        assert_eq!(byte_literals(": main 256"), None);
+    }
+}
@@ -0,0 +1,159 @@
+//! CHIP-8X: RCA's interpreter for the COSMAC VIP with the VP-590 colour board.
+//!
+//! The display is split into 8 columns of 8 pixels. Each column of each line has its own
+//! foreground colour, which `BXYN` sets, and the whole display shares one background colour,
+//! which `02A0` steps through blue, black, green and red.
+//!
+//! `BXY0` colours whole zones of 8x4 pixels. The low nibble of VX is the first zone column and
+//! its high nibble is how many more columns to colour. V(X+1) gives the zone rows the same way.
+//! `BXYN` colours N lines from the line in V(X+1), in the column holding pixel VX. Either way the
+//! colour is the low 3 bits of VY.
+
+use crate::machine::ColourFrame;
+use crate::state::pixel_bitmask;
+use std::ops::RangeInclusive;
+
+static COLUMN_WIDTH: usize = 8;
+static COLUMNS: usize = 8;
+static ZONE_HEIGHT: usize = 4;
+
+/// Background colours in the order `02A0` steps through them.
+static BACKGROUNDS: [[u8; 3]; 4] = [[0x00, 0x00, 0x80], [0x00, 0x00, 0x00], [0x00, 0x80, 0x00], [0x80, 0x00, 0x00]];
+
+/// Foreground colours by colour number.
+static FOREGROUNDS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00],
+    [0xFF, 0x00, 0x00],
+    [0x00, 0x00, 0xFF],
+    [0xFF, 0x00, 0xFF],
+    [0x00, 0xFF, 0x00],
+    [0xFF, 0xFF, 0x00],
+    [0x00, 0xFF, 0xFF],
+    [0xFF, 0xFF, 0xFF],
+];
+
+/// Red foreground, as the interpreter sets up at reset.
+static DEFAULT_FOREGROUND: u8 = 1;
+
+/// The VP-590's colour memory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColourBoard {
+    background: usize,
+    /// Foreground colour number of each column, one entry per display line.
+    foregrounds: Vec<[u8; 8]>,
+}
+
+impl ColourBoard {
+    pub fn new(rows: usize) -> Self {
+        Self {
+            background: 0,
+            foregrounds: vec![[DEFAULT_FOREGROUND; 8]; rows],
+        }
+    }
+
+    pub fn next_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    /// `BXY0`: colours the zones `horizontal` and `vertical` cover.
+    pub fn colour_zones(&mut self, horizontal: u8, vertical: u8, colour: u8) {
+        let rows = self.foregrounds.len();
+        for zone_row in nibble_range(vertical) {
+            for line in zone_row * ZONE_HEIGHT..((zone_row + 1) * ZONE_HEIGHT).min(rows) {
+                for column in nibble_range(horizontal).filter(|column| *column < COLUMNS) {
+                    self.foregrounds[line][column] = colour & 0x7;
+                }
+            }
+        }
+    }
+
+    /// `BXYN`: colours `lines` lines from `top` in the column holding pixel `x`.
+    pub fn colour_lines(&mut self, x: u8, top: u8, lines: usize, colour: u8) {
+        let rows = self.foregrounds.len();
+        let column = (x as usize % 64) / COLUMN_WIDTH;
+        for line in (top as usize..top as usize + lines).map(|line| line % rows) {
+            self.foregrounds[line][column] = colour & 0x7;
+        }
+    }
+
+    /// The monochrome display in the board's colours.
+    pub fn frame(&self, video: &[u128]) -> ColourFrame {
+        let background = BACKGROUNDS[self.background];
+        let pixels = video
+            .iter()
+            .zip(&self.foregrounds)
+            .flat_map(|(row, foregrounds)| {
+                (0..64).map(move |col| {
+                    if row & pixel_bitmask(col) != 0 {
+                        FOREGROUNDS[foregrounds[col / COLUMN_WIDTH] as usize]
+                    } else {
+                        background
+                    }
+                })
+            })
+            .collect();
+        ColourFrame {
+            width: 64,
+            height: video.len(),
+            pixels,
+        }
+    }
+}
+
+/// From the low nibble to the low nibble plus the high nibble.
+fn nibble_range(value: u8) -> RangeInclusive<usize> {
+    let start = (value & 0xF) as usize;
+    start..=start + (value >> 4) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The columns of `line` that have been given `colour`.
+    fn coloured(board: &ColourBoard, line: usize, colour: u8) -> Vec<usize> {
+        (0..COLUMNS).filter(|column| board.foregrounds[line][*column] == colour).collect()
+    }
+
+    #[test]
+    fn colours_zones_from_nibbles() {
+        let mut board = ColourBoard::new(32);
+        board.colour_zones(0x12, 0x01, 4);
+        for line in 4..8 {
+            assert_eq!(coloured(&board, line, 4), [2, 3], "line {}", line);
+        }
+        assert!(coloured(&board, 3, 4).is_empty());
+        assert!(coloured(&board, 8, 4).is_empty());
+    }
+
+    #[test]
+    fn clips_zones_to_the_display() {
+        let mut board = ColourBoard::new(32);
+        board.colour_zones(0x27, 0x17, 0x0E);
+        assert_eq!(coloured(&board, 31, 6), [7]);
+        assert!(coloured(&board, 27, 6).is_empty());
+    }
+
+    #[test]
+    fn colours_lines_in_one_column_wrapping_at_the_bottom() {
+        let mut board = ColourBoard::new(32);
+        board.colour_lines(20, 30, 4, 0x0F);
+        for line in [30, 31, 0, 1] {
+            assert_eq!(coloured(&board, line, 7), [2], "line {}", line);
+        }
+        assert!(coloured(&board, 2, 7).is_empty());
+    }
+
+    #[test]
+    fn frame_shows_lit_pixels_in_their_column_colour() {
+        let mut board = ColourBoard::new(2);
+        board.next_background();
+        board.colour_lines(8, 0, 1, 4);
+        let frame = board.frame(&[pixel_bitmask(0) | pixel_bitmask(8), 0]);
+        assert_eq!((frame.width, frame.height), (64, 2));
+        assert_eq!(frame.pixels[0], FOREGROUNDS[1]);
+        assert_eq!(frame.pixels[8], FOREGROUNDS[4]);
+        assert_eq!(frame.pixels[1], BACKGROUNDS[1]);
+        assert_eq!(frame.pixels[64 + 8], BACKGROUNDS[1]);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::font::Font;
+use crate::font::FontError;
+use crate::font::FontSet;
+use crate::init::Rom;
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::platform::PlatformName;
+use crate::platform::MAX_CYCLES_PER_TICK;
+use crate::state::KeyWait;
+use crate::state::Quirks;
+use crate::state::StackDepth;
+use crate::state::StackPolicy;
+use crate::state::Timing;
+use crate::trace::TraceFilter;
+use crate::trace::TraceFormat;
+use crate::trace::Tracer;
+use crate::vip::VipError;
+use crate::vip::VipImages;
+use clap::Parser;
+use clap::Subcommand;
+use clap::ValueEnum;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, Parser)]
+#[command(about = "A CHIP-8 emulator", args_conflicts_with_subcommands = true)]
+pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// ROM to load and start immediately
+    pub rom: Option<PathBuf>,
+
+    /// Instructions executed per second
+    #[arg(long)]
+    pub ips: Option<u32>,
+
+    /// Initial window size as a multiple of the 64x32 display
+    #[arg(long, default_value_t = 10)]
+    pub scale: u32,
+
+    /// Display colours
+    #[arg(long, value_parser = parse_palette, default_value = "classic")]
+    pub palette: Palette,
+
+    /// Load the ROM without starting it
+    #[arg(long)]
+    pub paused: bool,
+
+    /// Start in fullscreen
+    #[arg(long)]
+    pub fullscreen: bool,
+
+    /// Keymap file with one `<key> = <keypad hex digit>` line per key, or `<key> = 2:<hex digit>` for
+    /// CHIP-8X's second keypad
+    #[arg(long)]
+    pub keymap: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    #[command(flatten)]
+    pub strict: StrictArgs,
+
+    #[command(flatten)]
+    pub trace: TraceArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct StrictArgs {
+    /// Warn about writes below 0x200, reads of never-written memory, running code the program
+    /// wrote, and jumps to odd addresses or outside the ROM
+    #[arg(long)]
+    pub strict: bool,
+
+    /// Stop at the first strict mode warning
+    #[arg(long, requires = "strict")]
+    pub strict_pause: bool,
+}
+
+#[derive(Debug, Clone, Default, clap::Args)]
+pub struct MachineArgs {
+    /// Machine to emulate [default: guessed from the ROM]
+    #[arg(long, value_enum)]
+    pub platform: Option<PlatformName>,
+
+    /// Number of nested subroutine calls before the stack overflows [default: the platform's]
+    #[arg(long, value_enum)]
+    pub stack_depth: Option<StackDepth>,
+
+    /// What to do when the stack overflows or underflows
+    #[arg(long, value_enum, default_value_t = StackPolicy::Warn)]
+    pub stack_policy: StackPolicy,
+
+    /// What FX0A waits for [default: the platform's]
+    #[arg(long, value_enum)]
+    pub key_wait: Option<KeyWait>,
+
+    /// How much runs each frame: a fixed number of instructions, or as many as the COSMAC VIP
+    /// interpreter fits in its machine cycles [default: the platform's]
+    #[arg(long, value_enum)]
+    pub timing: Option<Timing>,
+
+    /// Built-in hex digit font [default: the platform's]
+    #[arg(long, value_enum)]
+    pub font: Option<FontSet>,
+
+    /// Raw font binary of 16 glyphs, all the same height, to use instead of a built-in font
+    #[arg(long, value_name = "FILE", conflicts_with = "font")]
+    pub font_file: Option<PathBuf>,
+
+    /// RCA monitor ROM, to run chip8, vip, hires and chip8e ROMs on an emulated COSMAC VIP instead of interpreting them
+    #[arg(long, value_name = "FILE", requires = "vip_interpreter")]
+    pub vip_monitor: Option<PathBuf>,
+
+    /// CHIP-8 interpreter image the emulated COSMAC VIP runs from 0x0000
+    #[arg(long, value_name = "FILE", requires = "vip_monitor")]
+    pub vip_interpreter: Option<PathBuf>,
+}
+
+impl MachineArgs {
+    /// The platform given with `--platform`, or the one `rom` looks to be written for.
+    pub fn platform(&self, rom_path: &Path, rom: &Rom) -> Platform {
+        self.platform.unwrap_or_else(|| PlatformName::detect(rom_path, rom)).platform()
+    }
+
+    /// Overrides the platform's quirks with those given on the command line.
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        if let Some(stack_depth) = self.stack_depth {
+            quirks.stack_depth = stack_depth;
+        }
+        if let Some(key_wait) = self.key_wait {
+            quirks.key_wait = key_wait;
+        }
+        if let Some(timing) = self.timing {
+            quirks.timing = timing;
+        }
+    }
+
+    /// The images for running on an emulated COSMAC VIP, if it was asked for.
+    pub fn vip_images(&self) -> Result<Option<VipImages>, VipError> {
+        match (&self.vip_monitor, &self.vip_interpreter) {
+            (Some(monitor), Some(interpreter)) => VipImages::from_files(monitor, interpreter).map(Some),
+            _ => Ok(None),
+        }
+    }
+
+    pub fn font(&self, platform: &Platform) -> Result<Font, FontError> {
+        match &self.font_file {
+            Some(path) => Font::from_file(path),
+            None => Ok(Font::Builtin(self.font.unwrap_or(platform.font))),
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run a ROM without a window until it halts, faults or reaches the frame limit
+    Run(RunArgs),
+    /// Run a ROM without a window and compare its execution trace with a reference trace
+    TraceDiff(TraceDiffArgs),
+    /// Run a ROM without a window and print where it spends its instructions
+    Profile(ProfileArgs),
+    /// Run a ROM without a window and report which bytes were executed, read and written
+    Coverage(CoverageArgs),
+}
+
+/// How to start a ROM without a window.
+#[derive(Debug, clap::Args)]
+pub struct HeadlessArgs {
+    /// ROM to run
+    pub rom: PathBuf,
+
+    /// Seed for the CXNN random number generator
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Input log of `<frame> <key> down|up` lines
+    #[arg(long)]
+    pub input: Option<PathBuf>,
+
+    /// Instructions executed per second
+    #[arg(long)]
+    pub ips: Option<u32>,
+
+    #[command(flatten)]
+    pub machine: MachineArgs,
+
+    #[command(flatten)]
+    pub strict: StrictArgs,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct RunArgs {
+    #[command(flatten)]
+    pub run: HeadlessArgs,
+
+    /// Give up after this many 60 Hz frames
+    #[arg(long, default_value_t = 36000)]
+    pub frames: u32,
+
+    /// Save the final screen as a PNG
+    #[arg(long, value_name = "FILE")]
+    pub screenshot: Option<PathBuf>,
+
+    /// Record every frame to a GIF
+    #[arg(long, value_name = "FILE")]
+    pub record: Option<PathBuf>,
+
+    /// Size of each CHIP-8 pixel in the screenshot and recording
+    #[arg(long, default_value_t = 10)]
+    pub scale: u32,
+
+    /// Screenshot and recording colours
+    #[arg(long, value_parser = parse_palette, default_value = "classic")]
+    pub palette: Palette,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TraceDiffArgs {
+    #[command(flatten)]
+    pub run: HeadlessArgs,
+
+    /// Trace to compare against, usually produced by another emulator
+    pub reference: PathBuf,
+
+    /// Format of the reference trace and of the trace written by this run
+    #[arg(long, value_enum, default_value_t = TraceFormat::Text)]
+    pub format: TraceFormat,
+
+    /// Where to write this emulator's trace
+    #[arg(long, default_value = "trace-diff.log")]
+    pub output: PathBuf,
+
+    /// Give up after this many 60 Hz frames
+    #[arg(long, default_value_t = 36000)]
+    pub max_frames: u32,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct ProfileArgs {
+    #[command(flatten)]
+    pub run: HeadlessArgs,
+
+    /// Number of 60 Hz frames to run
+    #[arg(long, default_value_t = 600)]
+    pub frames: u32,
+
+    /// Number of hot addresses to list
+    #[arg(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct CoverageArgs {
+    #[command(flatten)]
+    pub run: HeadlessArgs,
+
+    /// Number of 60 Hz frames to run
+    #[arg(long, default_value_t = 600)]
+    pub frames: u32,
+
+    /// Report format
+    #[arg(long, value_enum, default_value_t = CoverageFormat::Listing)]
+    pub format: CoverageFormat,
+
+    /// Write the report here instead of to standard output
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum CoverageFormat {
+    /// Annotated listing of the ROM
+    Listing,
+    /// Executed, read and written address ranges
+    Json,
+}
+
+#[derive(Debug, clap::Args)]
+pub struct TraceArgs {
+    /// Log every executed instruction to this file
+    #[arg(long = "trace", value_name = "FILE")]
+    pub path: Option<PathBuf>,
+
+    /// Trace file format
+    #[arg(long = "trace-format", value_enum, default_value_t = TraceFormat::Text)]
+    pub format: TraceFormat,
+
+    /// Only trace instructions in this hex address range, e.g. 200-2FF
+    #[arg(long = "trace-range", value_parser = parse_address_range, value_name = "START-END")]
+    pub range: Option<RangeInclusive<u16>>,
+
+    /// Only trace these instructions, given as opcode patterns (DXYN) or mnemonics (DRW)
+    #[arg(long = "trace-instructions", value_delimiter = ',', value_name = "LIST")]
+    pub instructions: Vec<String>,
+
+    /// Keep only the last N instructions and write them out when the CPU faults
+    #[arg(long = "trace-ring", value_name = "N")]
+    pub ring: Option<usize>,
+}
+
+impl TraceArgs {
+    pub fn tracer(&self) -> io::Result<Option<Tracer>> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let filter = TraceFilter::new(self.range.clone(), &self.instructions);
+        match self.ring {
+            Some(capacity) => Ok(Some(Tracer::ring_buffer(path, self.format, filter, capacity))),
+            None => Tracer::to_file(path, self.format, filter).map(Some),
+        }
+    }
+}
+
+/// Converts an instructions-per-second setting into instructions per timer tick.
+pub fn cycles_per_tick(ips: u32, ticks_per_second: u8) -> u16 {
+    (ips as f64 / ticks_per_second as f64).round().clamp(1., MAX_CYCLES_PER_TICK as f64) as u16
+}
+
+fn parse_address_range(range: &str) -> Result<RangeInclusive<u16>, String> {
+    let parse_address = |address: &str| u16::from_str_radix(address.trim().trim_start_matches("0x"), 16).map_err(|error| format!("invalid address {:?}: {}", address, error));
+    let (start, end) = range.split_once('-').ok_or_else(|| String::from("expected START-END"))?;
+    Ok(parse_address(start)?..=parse_address(end)?)
+}
+
+fn parse_palette(name: &str) -> Result<Palette, String> {
+    Palette::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = Palette::PRESETS.iter().map(Palette::name).collect();
+        format!("unknown palette {:?}, expected one of: {}", name, names.join(", "))
+    })
+}
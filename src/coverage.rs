@@ -0,0 +1,198 @@
+use crate::disasm;
+use crate::state::State;
+use serde_json::json;
+use std::fmt::Write;
+use std::ops::Range;
+use std::ops::RangeInclusive;
+
+/// How a byte of memory has been used since coverage tracking started.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Access {
+    pub executed: bool,
+    /// The first byte of an executed instruction, as opposed to its second byte.
+    pub instruction: bool,
+    pub read: bool,
+    pub written: bool,
+}
+
+impl Access {
+    pub fn is_touched(&self) -> bool {
+        self.executed || self.read || self.written
+    }
+
+    /// `XRW` flags with `-` for each access that hasn't happened.
+    pub fn flags(&self) -> String {
+        [(self.executed, 'X'), (self.read, 'R'), (self.written, 'W')]
+            .iter()
+            .map(|(set, flag)| if *set { *flag } else { '-' })
+            .collect()
+    }
+}
+
+/// Per-byte record of which memory has been executed, read as data and written.
+#[derive(Debug, Clone)]
+pub struct Coverage {
+    bytes: Vec<Access>,
+}
+
+impl Coverage {
+    /// Starts tracking a machine with `memory_size` bytes of memory.
+    pub fn new(memory_size: usize) -> Self {
+        Self {
+            bytes: vec![Access::default(); memory_size],
+        }
+    }
+
+    /// Marks the instruction's own bytes and the memory it will touch, so must be called before it runs.
+    pub fn record(&mut self, state: &State, pc: u16, opcode: u16) {
+        let length = self.bytes.len();
+        self.bytes[pc as usize % length].instruction = true;
+        self.mark(pc as u32, 2, |access| access.executed = true);
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        match disasm::pattern(opcode) {
+            "DXYN" => self.mark(state.index, (opcode & 0x000F) as usize, |access| access.read = true),
+            "FX65" => self.mark(state.index, x + 1, |access| access.read = true),
+            "FX55" => self.mark(state.index, x + 1, |access| access.written = true),
+            "FX33" => self.mark(state.index, 3, |access| access.written = true),
+            _ => {}
+        }
+    }
+
+    fn mark(&mut self, start: u32, length: usize, set: impl Fn(&mut Access)) {
+        for offset in 0..length {
+            let length = self.bytes.len();
+            set(&mut self.bytes[(start as usize + offset) % length]);
+        }
+    }
+
+    pub fn access(&self, address: usize) -> Access {
+        self.bytes[address % self.bytes.len()]
+    }
+
+    /// One line per instruction or data byte in `rom` and every other byte that was touched,
+    /// with untouched stretches outside the ROM left out.
+    pub fn listing(&self, memory: &[u8], rom: Range<usize>) -> String {
+        let mut listing = String::from("ADDR  BYTES  XRW  CONTENTS\n");
+        let mut address = 0;
+        let mut skipped = false;
+        let end = memory.len().min(self.bytes.len());
+        while address < end {
+            let access = self.bytes[address];
+            if !rom.contains(&address) && !access.is_touched() {
+                skipped = true;
+                address += 1;
+                continue;
+            }
+            if skipped {
+                listing.push_str("...\n");
+                skipped = false;
+            }
+            if access.instruction && address + 1 < end {
+                let opcode = u16::from_be_bytes([memory[address], memory[address + 1]]);
+                let _ = writeln!(
+                    listing,
+                    "{:03X}   {:02X} {:02X}  {}  {}",
+                    address,
+                    memory[address],
+                    memory[address + 1],
+                    access.flags(),
+                    disasm::disassemble(opcode)
+                );
+                address += 2;
+            } else {
+                let bits: String = (0..8).rev().map(|bit| if memory[address] & (1 << bit) != 0 { '#' } else { '.' }).collect();
+                let _ = writeln!(listing, "{:03X}   {:02X}     {}  {}", address, memory[address], access.flags(), bits);
+                address += 1;
+            }
+        }
+        listing
+    }
+
+    /// Address ranges, as inclusive `[start, end]` pairs, that were executed, read and written.
+    pub fn to_json(&self) -> String {
+        let ranges = |touched: fn(&Access) -> bool| -> Vec<[usize; 2]> { self.ranges(touched).map(|range| [*range.start(), *range.end()]).collect() };
+        let coverage = json!({
+            "executed": ranges(|access| access.executed),
+            "read": ranges(|access| access.read),
+            "written": ranges(|access| access.written),
+        });
+        serde_json::to_string_pretty(&coverage).expect("coverage is always valid JSON")
+    }
+
+    fn ranges(&self, touched: fn(&Access) -> bool) -> impl Iterator<Item = RangeInclusive<usize>> + '_ {
+        let mut address = 0;
+        std::iter::from_fn(move || {
+            let start = (address..self.bytes.len()).find(|address| touched(&self.bytes[*address]))?;
+            let end = (start..self.bytes.len()).find(|address| !touched(&self.bytes[*address])).unwrap_or(self.bytes.len());
+            address = end;
+            Some(start..=end - 1)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Font;
+    use crate::init;
+    use crate::platform::PlatformName;
+
+    fn state(name: PlatformName) -> State {
+        let platform = name.platform();
+        init::init_state(&[0xA3, 0x00, 0xF0, 0x55, 0xD0, 0x12, 0x12, 0x06], platform, platform.quirks, &Font::default()).unwrap()
+    }
+
+    #[test]
+    fn records_executed_read_and_written_bytes() {
+        let mut state = state(PlatformName::Chip8);
+        let mut coverage = Coverage::new(state.memory.len());
+        coverage.record(&state, 0x200, 0xA300);
+        state.index = 0x300;
+        coverage.record(&state, 0x202, 0xF155);
+        coverage.record(&state, 0x204, 0xD012);
+        assert_eq!(
+            coverage.access(0x200),
+            Access {
+                executed: true,
+                instruction: true,
+                read: false,
+                written: false
+            }
+        );
+        assert_eq!(coverage.access(0x201), Access { executed: true, ..Access::default() });
+        assert_eq!(coverage.access(0x300).flags(), "-RW");
+        assert_eq!(coverage.access(0x301).flags(), "-RW");
+        assert_eq!(coverage.access(0x302).flags(), "---");
+        assert_eq!(
+            coverage.to_json(),
+            serde_json::to_string_pretty(&json!({ "executed": [[0x200, 0x205]], "read": [[0x300, 0x301]], "written": [[0x300, 0x301]] })).unwrap()
+        );
+    }
+
+    #[test]
+    fn lists_the_rom_and_touched_bytes() {
+        let mut state = state(PlatformName::Chip8);
+        let mut coverage = Coverage::new(state.memory.len());
+        coverage.record(&state, 0x200, 0xA300);
+        state.index = 0x300;
+        coverage.record(&state, 0x202, 0xF055);
+        let listing = coverage.listing(&state.memory, 0x200..0x208);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(lines[..2], ["ADDR  BYTES  XRW  CONTENTS", "..."]);
+        assert!(lines[2].starts_with("200   A3 00  X--  "), "{}", lines[2]);
+        assert!(lines[3].starts_with("202   F0 55  X--  "), "{}", lines[3]);
+        assert_eq!(lines[4], "204   D0     ---  ##.#....");
+        assert_eq!(lines[8..], ["...", "300   00     --W  ........"]);
+    }
+
+    #[test]
+    fn tracks_all_of_megachip_memory() {
+        let mut state = state(PlatformName::MegaChip);
+        let mut coverage = Coverage::new(state.memory.len());
+        state.index = 0x10200;
+        coverage.record(&state, 0x200, 0xF055);
+        assert!(coverage.access(0x10200).written);
+        // Beyond 64 KiB, so doesn't alias the program
+        assert!(!coverage.access(0x200).written);
+    }
+}
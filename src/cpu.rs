@@ -1,5 +1,98 @@
+use crate::coverage::Coverage;
 use crate::opcodes;
+use crate::platform::PlatformName;
+use crate::profiler::Profiler;
 use crate::state::State;
+use crate::state::Timing;
+use crate::strict::StrictMode;
+use crate::timing;
+use crate::trace::Tracer;
+use std::fmt;
+
+/// A condition that stops the CPU, leaving `pc` at the offending instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CpuFault {
+    InvalidOpcode {
+        address: u16,
+        opcode: u16,
+    },
+    StackOverflow {
+        address: u16,
+        depth: usize,
+    },
+    StackUnderflow {
+        address: u16,
+    },
+    WroteBelowProgram {
+        address: u16,
+        target: u16,
+    },
+    ReadUninitialisedMemory {
+        address: u16,
+        target: u16,
+    },
+    ExecutedWrittenMemory {
+        address: u16,
+    },
+    BadJump {
+        address: u16,
+        target: u16,
+    },
+    /// A real instruction this emulator doesn't implement.
+    Unsupported {
+        address: u16,
+        opcode: u16,
+        reason: &'static str,
+    },
+}
+
+impl fmt::Display for CpuFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuFault::InvalidOpcode { address, opcode } => write!(f, "invalid opcode {:04X} at {:03X}", opcode, address),
+            CpuFault::StackOverflow { address, depth } => write!(f, "stack overflow at {:03X}: more than {} nested calls", address, depth),
+            CpuFault::StackUnderflow { address } => write!(f, "stack underflow at {:03X}: return without a call", address),
+            CpuFault::WroteBelowProgram { address, target } => write!(f, "write to {:03X} below the program at {:03X}", target, address),
+            CpuFault::ReadUninitialisedMemory { address, target } => write!(f, "read of never-written memory at {:03X} by {:03X}", target, address),
+            CpuFault::ExecutedWrittenMemory { address } => write!(f, "executing code written by the program at {:03X}", address),
+            CpuFault::BadJump { address, target } => {
+                let reason = if target % 2 == 1 { "an odd address" } else { "outside the ROM" };
+                write!(f, "jump to {} {:03X} at {:03X}", reason, target, address)
+            }
+            CpuFault::Unsupported { address, opcode, reason } => write!(f, "unsupported opcode {:04X} at {:03X}: {}", opcode, address, reason),
+        }
+    }
+}
+
+/// Why a program has stopped making progress on its own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Halt {
+    /// A `1NNN` that jumps to itself, the usual way for a program to end.
+    SelfJump { address: u16 },
+    /// An `FX0A` waiting for a key press.
+    WaitingForKey { address: u16 },
+    /// A CHIP-8E `00ED`, which stops the program.
+    Stopped { address: u16 },
+}
+
+impl fmt::Display for Halt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Halt::SelfJump { address } => write!(f, "jump to itself at {:03X}", address),
+            Halt::WaitingForKey { address } => write!(f, "waiting for a key at {:03X}", address),
+            Halt::Stopped { address } => write!(f, "stopped at {:03X}", address),
+        }
+    }
+}
+
+/// Optional instrumentation driven by the CPU loop.
+#[derive(Debug, Default)]
+pub struct Hooks {
+    pub tracer: Option<Tracer>,
+    pub profiler: Option<Profiler>,
+    pub coverage: Option<Coverage>,
+    pub strict: Option<StrictMode>,
+}
 
 static OPCODE_TABLE: [fn(&mut State, u16); 16] = [
     op_table_0,
@@ -39,6 +132,26 @@ static OPCODE_TABLE_0: [fn(&mut State, u16); 16] = [
     op_none,
 ];
 
+/// `0NNN` on MegaChip, by the second nibble. `00NN` goes on to `op_table_mega_0`.
+static OPCODE_TABLE_MEGA: [fn(&mut State, u16); 16] = [
+    op_table_mega_0,
+    opcodes::op_01NN,
+    opcodes::op_02NN,
+    opcodes::op_03NN,
+    opcodes::op_04NN,
+    opcodes::op_05NN,
+    opcodes::op_060N,
+    opcodes::op_0700,
+    opcodes::op_080N,
+    opcodes::op_09NN,
+    op_none,
+    op_none,
+    op_none,
+    op_none,
+    op_none,
+    op_none,
+];
+
 static OPCODE_TABLE_8: [fn(&mut State, u16); 16] = [
     opcodes::op_8XY0,
     opcodes::op_8XY1,
@@ -126,7 +239,7 @@ static OPCODE_TABLE_F: [fn(&mut State, u16); 102] = [
     op_none,
     op_none,
     op_none,
-    op_none,
+    opcodes::op_FX30,
     op_none,
     op_none,
     opcodes::op_FX33,
@@ -182,14 +295,30 @@ static OPCODE_TABLE_F: [fn(&mut State, u16); 102] = [
     opcodes::op_FX65,
 ];
 
-fn op_none(_state: &mut State, opcode: u16) {
-    // TODO: what should we do here?
-    panic!("Invalid opcode: {:#06x}", opcode);
+fn op_none(state: &mut State, opcode: u16) {
+    state.fault = Some(CpuFault::InvalidOpcode {
+        address: opcodes::instruction_address(state),
+        opcode,
+    });
 }
 
 fn op_table_0(state: &mut State, opcode: u16) {
-    let index = (opcode & 0x000F) as usize;
-    OPCODE_TABLE_0[index](state, opcode);
+    if state.mega.is_some() {
+        let index = ((opcode & 0x0F00) >> 8) as usize;
+        OPCODE_TABLE_MEGA[index](state, opcode);
+    } else {
+        let index = (opcode & 0x000F) as usize;
+        OPCODE_TABLE_0[index](state, opcode);
+    }
+}
+
+fn op_table_mega_0(state: &mut State, opcode: u16) {
+    match opcode {
+        0x0010 => opcodes::op_0010(state, opcode),
+        0x0011 => opcodes::op_0011(state, opcode),
+        0x00E0 | 0x00EE => OPCODE_TABLE_0[(opcode & 0x000F) as usize](state, opcode),
+        _ => op_none(state, opcode),
+    }
 }
 
 fn op_table_8(state: &mut State, opcode: u16) {
@@ -204,28 +333,404 @@ fn op_table_e(state: &mut State, opcode: u16) {
 
 fn op_table_f(state: &mut State, opcode: u16) {
     let index = (opcode & 0x00FF) as usize;
-    if index > OPCODE_TABLE_F.len() {
+    if index >= OPCODE_TABLE_F.len() {
         op_none(state, opcode);
     } else {
         OPCODE_TABLE_F[index](state, opcode);
     }
 }
 
+/// The CHIP-8E, CHIP-8X or SUPER-CHIP instruction `opcode` is on `platform`, where those add to
+/// or replace the usual instructions. MegaChip takes SUPER-CHIP's.
+fn variant_op(platform: PlatformName, opcode: u16) -> Option<fn(&mut State, u16)> {
+    let nibble = opcode & 0x000F;
+    let byte = opcode & 0x00FF;
+    let op: fn(&mut State, u16) = match (platform, opcode >> 12) {
+        (PlatformName::Chip8E, 0x0) => match opcode {
+            0x00ED => opcodes::op_00ED,
+            0x00F2 => opcodes::op_00F2,
+            0x0151 => opcodes::op_0151,
+            0x0188 => opcodes::op_0188,
+            _ => return None,
+        },
+        (PlatformName::Chip8E, 0x5) => match nibble {
+            0x1 => opcodes::op_5XY1_8E,
+            0x2 => opcodes::op_5XY2,
+            0x3 => opcodes::op_5XY3,
+            _ => return None,
+        },
+        (PlatformName::Chip8E, 0x9) => match nibble {
+            0x1 => opcodes::op_9XY1,
+            0x2 => opcodes::op_9XY2,
+            0x3 => opcodes::op_9XY3,
+            _ => return None,
+        },
+        (PlatformName::Chip8E, 0xB) => match opcode & 0x0F00 {
+            0x0B00 => opcodes::op_BBNN,
+            0x0F00 => opcodes::op_BFNN,
+            _ => return None,
+        },
+        (PlatformName::Chip8E, 0xF) => match byte {
+            0x03 => opcodes::op_FX03,
+            0x1B => opcodes::op_FX1B,
+            0x4F => opcodes::op_FX4F,
+            0xE3 => opcodes::op_FXE3,
+            0xE7 => opcodes::op_FXE7,
+            _ => return None,
+        },
+        (PlatformName::Chip8X, 0x0) if opcode == 0x02A0 => opcodes::op_02A0,
+        (PlatformName::Chip8X, 0x5) if nibble == 0x1 => opcodes::op_5XY1_8X,
+        (PlatformName::Chip8X, 0xB) => opcodes::op_BXYN,
+        (PlatformName::Chip8X, 0xE) => match byte {
+            0xF2 => opcodes::op_EXF2,
+            0xF5 => opcodes::op_EXF5,
+            _ => return None,
+        },
+        (PlatformName::Chip8X, 0xF) => match byte {
+            0xF8 => opcodes::op_FXF8,
+            0xFB => opcodes::op_FXFB,
+            _ => return None,
+        },
+        (PlatformName::Schip | PlatformName::MegaChip, 0x0) => match opcode {
+            0x00C0..=0x00CF => opcodes::op_00CN,
+            0x00FB => opcodes::op_00FB,
+            0x00FC => opcodes::op_00FC,
+            0x00FD => opcodes::op_00FD,
+            0x00FE => opcodes::op_00FE,
+            0x00FF => opcodes::op_00FF,
+            _ => return None,
+        },
+        (PlatformName::Schip | PlatformName::MegaChip, 0xF) => match byte {
+            0x75 => opcodes::op_FX75,
+            0x85 => opcodes::op_FX85,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    Some(op)
+}
+
 fn run_opcode(state: &mut State, opcode: u16) {
+    if let Some(op) = variant_op(state.platform.name, opcode) {
+        op(state, opcode);
+        return;
+    }
     let index = ((opcode & 0xF000) >> 12) as usize;
     OPCODE_TABLE[index](state, opcode);
 }
 
-pub fn run_cycle(state: &mut State, cycles_per_tick: u8) {
-    for _ in 0..cycles_per_tick {
-        let opcode = ((state.memory[state.pc as usize] as u16) << 8) | state.memory[(state.pc.wrapping_add(1) % 4096) as usize] as u16;
-        state.pc = state.pc.wrapping_add(2) % 4096;
-        run_opcode(state, opcode);
+/// Runs one tick's worth of instructions and then decrements the timers. Stops early, without
+/// touching the timers, if an instruction faults.
+///
+/// A tick is `cycles_per_tick` instructions, or under VIP timing as many as fit in the VIP's
+/// machine cycles for a frame. A VIP draws sprites only just after the display interrupt, so a
+/// DXYN part way through a frame waits for the next one.
+pub fn run_cycle(state: &mut State, cycles_per_tick: u16, hooks: &mut Hooks) -> Result<(), CpuFault> {
+    let vip_timing = state.quirks.timing == Timing::Vip;
+    let mut executed = 0;
+    let mut used_cycles = state.cycle_debt;
+    loop {
+        let frame_done = if vip_timing {
+            used_cycles >= timing::VIP_CYCLES_PER_FRAME
+        } else {
+            executed == cycles_per_tick as u32
+        };
+        if frame_done {
+            break;
+        }
+        let pc = state.pc;
+        let opcode = ((state.memory[pc as usize] as u16) << 8) | state.memory[state.offset(pc, 1) as usize] as u16;
+        if vip_timing && opcode >> 12 == 0xD && executed > 0 {
+            used_cycles = timing::VIP_CYCLES_PER_FRAME;
+            break;
+        }
+        if vip_timing {
+            used_cycles += timing::vip_cycles(state, opcode);
+        }
+        executed += 1;
+        let checked = match &mut hooks.strict {
+            Some(strict) => strict.check(state, pc, opcode),
+            None => Ok(()),
+        };
+        if checked.is_ok() {
+            if let Some(tracer) = &mut hooks.tracer {
+                tracer.record(state, pc, opcode);
+            }
+            if let Some(profiler) = &mut hooks.profiler {
+                profiler.record(pc, opcode);
+            }
+            if let Some(coverage) = &mut hooks.coverage {
+                coverage.record(state, pc, opcode);
+            }
+            state.pc = state.offset(pc, 2);
+            state.halted = None;
+            run_opcode(state, opcode);
+            if vip_timing && matches!(state.halted, Some(Halt::WaitingForKey { .. })) {
+                // The VIP does nothing else until a key comes
+                used_cycles = used_cycles.max(timing::VIP_CYCLES_PER_FRAME);
+            }
+        }
+        if let Some(fault) = checked.err().or_else(|| state.fault.take()) {
+            state.pc = pc;
+            if let Some(tracer) = &mut hooks.tracer {
+                if let Err(error) = tracer.dump() {
+                    eprintln!("Could not write trace to {}: {}", tracer.path().display(), error);
+                }
+            }
+            return Err(fault);
+        }
+    }
+    if let Some(tracer) = &mut hooks.tracer {
+        tracer.flush();
+    }
+    state.cycle_debt = used_cycles.saturating_sub(timing::VIP_CYCLES_PER_FRAME);
+    if let Some(mega) = &mut state.mega {
+        mega.end_frame(&state.memory);
     }
+    state.keypad.end_frame();
+    state.keypad2.end_frame();
     if state.delay_timer > 0 {
         state.delay_timer -= 1;
     }
     if state.sound_timer > 0 {
         state.sound_timer -= 1;
     }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Font;
+    use crate::font::FontSet;
+    use crate::init;
+    use crate::state::KeyWait;
+    use crate::state::Quirks;
+    use crate::state::StackDepth;
+    use crate::state::StackPolicy;
+
+    fn machine(name: PlatformName) -> State {
+        let platform = name.platform();
+        init::init_state(&[0x00, 0xE0], platform, platform.quirks, &Font::Builtin(FontSet::Octo)).unwrap()
+    }
+
+    #[test]
+    fn chip8x_adds_nibbles_wrapping_at_8() {
+        let mut state = machine(PlatformName::Chip8X);
+        state.registers[1] = 0x36;
+        state.registers[2] = 0x25;
+        run_opcode(&mut state, 0x5121);
+        assert_eq!(state.registers[1], 0x53);
+        state.registers[1] = 0x77;
+        state.registers[2] = 0x11;
+        run_opcode(&mut state, 0x5121);
+        assert_eq!(state.registers[1], 0x00);
+        assert_eq!(state.registers[2], 0x11);
+    }
+
+    #[test]
+    fn chip8e_reads_5xy1_as_a_skip() {
+        let mut state = machine(PlatformName::Chip8E);
+        state.pc = 0x202;
+        state.registers[1] = 0x36;
+        state.registers[2] = 0x25;
+        run_opcode(&mut state, 0x5121);
+        assert_eq!((state.registers[1], state.pc), (0x36, 0x204));
+    }
+
+    #[test]
+    fn megachip_scrolls_like_super_chip() {
+        let mut state = machine(PlatformName::MegaChip);
+        state.video[0] = 0xF000_0000_0000_000F << 64;
+        run_opcode(&mut state, 0x00C2);
+        assert_eq!((state.video[0], state.video[2]), (0, 0xF000_0000_0000_000F << 64));
+        run_opcode(&mut state, 0x00FB);
+        assert_eq!(state.video[2], 0x0F00_0000_0000_0000 << 64);
+        run_opcode(&mut state, 0x00FC);
+        assert_eq!(state.video[2], 0xF000_0000_0000_0000 << 64);
+    }
+
+    #[test]
+    fn super_chip_draws_low_res_pixels_as_2x2_blocks() {
+        let mut state = machine(PlatformName::Schip);
+        assert_eq!(state.video.len(), 64);
+        state.memory[0x300] = 0x80;
+        state.index = 0x300;
+        state.registers[..2].copy_from_slice(&[63, 1]);
+        run_opcode(&mut state, 0xD011);
+        assert_eq!(state.video[..4], [0, 0, 0b11, 0b11]);
+        run_opcode(&mut state, 0xD011);
+        assert_eq!((state.video[2], state.registers[15]), (0, 1));
+    }
+
+    #[test]
+    fn super_chip_draws_16x16_sprites_in_hi_res() {
+        let mut state = machine(PlatformName::Schip);
+        run_opcode(&mut state, 0x00FF);
+        assert!(state.hires && state.fault.is_none());
+        state.memory[0x300..0x320].fill(0xFF);
+        state.index = 0x300;
+        state.registers[..2].copy_from_slice(&[120, 60]);
+        run_opcode(&mut state, 0xD010);
+        // Clipped at the right and bottom edges
+        assert_eq!(state.video[60..], [0xFF; 4]);
+        assert_eq!(state.video[59], 0);
+        run_opcode(&mut state, 0x00FB);
+        assert_eq!(state.video[60], 0x0F);
+        run_opcode(&mut state, 0x00FE);
+        assert!(!state.hires);
+    }
+
+    #[test]
+    fn megachip_saves_and_restores_flag_registers() {
+        let mut state = machine(PlatformName::MegaChip);
+        state.registers[..9].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        run_opcode(&mut state, 0xFF75);
+        state.registers = [0; 16];
+        run_opcode(&mut state, 0xF285);
+        assert_eq!(state.registers[..4], [1, 2, 3, 0]);
+        run_opcode(&mut state, 0xFF85);
+        assert_eq!(state.registers[..9], [1, 2, 3, 4, 5, 6, 7, 8, 0]);
+    }
+
+    #[test]
+    fn megachip_exits_and_refuses_super_chip_hi_res() {
+        let mut state = machine(PlatformName::MegaChip);
+        state.pc = 0x202;
+        run_opcode(&mut state, 0x00FD);
+        assert_eq!(state.halted, Some(Halt::Stopped { address: 0x200 }));
+        run_opcode(&mut state, 0x00FE);
+        run_opcode(&mut state, 0x00FF);
+        assert!(matches!(state.fault, Some(CpuFault::Unsupported { opcode: 0x00FF, .. })));
+        state.fault = None;
+        run_opcode(&mut state, 0x0011);
+        run_opcode(&mut state, 0x00FF);
+        assert_eq!(state.fault, None);
+    }
+
+    fn stack(depth: StackDepth, policy: StackPolicy) -> State {
+        let mut state = machine(PlatformName::Chip8);
+        state.quirks.stack_depth = depth;
+        state.stack_policy = policy;
+        state.pc = 0x202;
+        state
+    }
+
+    #[test]
+    fn stack_overflow_is_reported_once_per_policy() {
+        let overflow = CpuFault::StackOverflow { address: 0x200, depth: 12 };
+        for (policy, fault, warnings) in [
+            (StackPolicy::Halt, Some(overflow), vec![]),
+            (StackPolicy::Warn, None, vec![overflow]),
+            (StackPolicy::Wrap, None, vec![]),
+        ] {
+            let mut state = stack(StackDepth::Twelve, policy);
+            for _ in 0..12 {
+                run_opcode(&mut state, 0x2202);
+            }
+            assert_eq!((state.fault, state.warnings.len()), (None, 0), "{}", policy);
+            run_opcode(&mut state, 0x2300);
+            assert_eq!((state.fault, &state.warnings), (fault, &warnings), "{}", policy);
+            // Wrapping overwrites the oldest return address
+            let expected_sp = if fault.is_some() { 12 } else { 1 };
+            assert_eq!(state.sp, expected_sp, "{}", policy);
+        }
+    }
+
+    #[test]
+    fn stack_underflow_is_reported_once_per_policy() {
+        let underflow = CpuFault::StackUnderflow { address: 0x200 };
+        for (policy, fault, warnings) in [
+            (StackPolicy::Halt, Some(underflow), vec![]),
+            (StackPolicy::Warn, None, vec![underflow]),
+            (StackPolicy::Wrap, None, vec![]),
+        ] {
+            let mut state = stack(StackDepth::Sixteen, policy);
+            run_opcode(&mut state, 0x00EE);
+            assert_eq!((state.fault, &state.warnings), (fault, &warnings), "{}", policy);
+            if fault.is_none() {
+                assert_eq!(state.sp, 15, "{}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn unlimited_stack_underflow_with_nothing_to_wrap_to_only_faults() {
+        for policy in StackPolicy::ALL {
+            let mut state = stack(StackDepth::Unlimited, policy);
+            run_opcode(&mut state, 0x00EE);
+            assert_eq!(state.fault, Some(CpuFault::StackUnderflow { address: 0x200 }), "{}", policy);
+            assert!(state.warnings.is_empty(), "{}", policy);
+        }
+        let mut state = stack(StackDepth::Unlimited, StackPolicy::Wrap);
+        run_opcode(&mut state, 0x2300);
+        run_opcode(&mut state, 0x00EE);
+        run_opcode(&mut state, 0x00EE);
+        assert_eq!((state.fault, state.sp, state.pc), (None, 0, 0x202));
+    }
+
+    fn waiting_for_a_key(key_wait: KeyWait) -> State {
+        let quirks = Quirks { key_wait, ..Quirks::default() };
+        init::init_state(&[0xF3, 0x0A, 0x12, 0x02], PlatformName::Chip8.platform(), quirks, &Font::default()).unwrap()
+    }
+
+    /// Runs one instruction a frame and returns the key FX0A stored, if it has finished waiting.
+    fn frame(state: &mut State) -> Option<u8> {
+        run_cycle(state, 1, &mut Hooks::default()).unwrap();
+        (state.pc == 0x202).then_some(state.registers[3])
+    }
+
+    #[test]
+    fn key_wait_held_takes_a_key_held_from_before() {
+        let mut state = waiting_for_a_key(KeyWait::Held);
+        state.keypad.press(0x5);
+        state.keypad.end_frame();
+        assert_eq!(frame(&mut state), Some(0x5));
+    }
+
+    #[test]
+    fn key_wait_press_needs_a_key_to_go_down() {
+        let mut state = waiting_for_a_key(KeyWait::Press);
+        state.keypad.press(0x5);
+        state.keypad.end_frame();
+        assert_eq!(frame(&mut state), None);
+        state.keypad.press(0x9);
+        assert_eq!(frame(&mut state), Some(0x9));
+    }
+
+    #[test]
+    fn key_wait_release_needs_a_press_and_release_while_waiting() {
+        let mut state = waiting_for_a_key(KeyWait::Release);
+        state.keypad.press(0x5);
+        state.keypad.end_frame();
+        assert_eq!(frame(&mut state), None);
+        // Held since before the wait, so letting go doesn't count
+        state.keypad.release(0x5);
+        assert_eq!(frame(&mut state), None);
+        state.keypad.press(0x9);
+        assert_eq!(frame(&mut state), None);
+        state.keypad.release(0x9);
+        assert_eq!(frame(&mut state), Some(0x9));
+    }
+
+    #[test]
+    fn halts_on_a_jump_to_itself() {
+        let mut state = init::init_state(&[0x60, 0x01, 0x12, 0x02], PlatformName::Chip8.platform(), Quirks::default(), &Font::default()).unwrap();
+        run_cycle(&mut state, 1, &mut Hooks::default()).unwrap();
+        assert_eq!(state.halted, None);
+        run_cycle(&mut state, 4, &mut Hooks::default()).unwrap();
+        assert_eq!(state.halted, Some(Halt::SelfJump { address: 0x202 }));
+        assert_eq!(state.pc, 0x202);
+    }
+
+    #[test]
+    fn halts_while_waiting_for_a_key_until_one_comes() {
+        let mut state = init::init_state(&[0xF3, 0x0A, 0x12, 0x02], PlatformName::Chip8.platform(), Quirks::default(), &Font::default()).unwrap();
+        run_cycle(&mut state, 4, &mut Hooks::default()).unwrap();
+        assert_eq!(state.halted, Some(Halt::WaitingForKey { address: 0x200 }));
+        assert_eq!(state.pc, 0x200);
+        state.keypad.press(0x7);
+        run_cycle(&mut state, 1, &mut Hooks::default()).unwrap();
+        assert_eq!((state.halted, state.registers[3], state.pc), (None, 0x7, 0x202));
+    }
 }
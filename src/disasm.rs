@@ -0,0 +1,183 @@
+/// The opcode pattern an instruction matches, such as `8XY4`, or `????` for an unknown opcode.
+pub fn pattern(opcode: u16) -> &'static str {
+    match (opcode & 0xF000) >> 12 {
+        0x0 => match opcode {
+            0x00E0 => "00E0",
+            0x00EE => "00EE",
+            0x0010 => "0010",
+            0x0011 => "0011",
+            0x00C0..=0x00CF => "00CN",
+            0x00FB => "00FB",
+            0x00FC => "00FC",
+            0x00FD => "00FD",
+            0x00FE => "00FE",
+            0x00FF => "00FF",
+            0x0100..=0x01FF => "01NN",
+            0x0200..=0x02FF => "02NN",
+            0x0300..=0x03FF => "03NN",
+            0x0400..=0x04FF => "04NN",
+            0x0500..=0x05FF => "05NN",
+            0x0600..=0x060F => "060N",
+            0x0700 => "0700",
+            0x0800..=0x080F => "080N",
+            0x0900..=0x09FF => "09NN",
+            _ => "0NNN",
+        },
+        0x1 => "1NNN",
+        0x2 => "2NNN",
+        0x3 => "3XNN",
+        0x4 => "4XNN",
+        0x5 if opcode & 0x000F == 0 => "5XY0",
+        0x6 => "6XNN",
+        0x7 => "7XNN",
+        0x8 => match opcode & 0x000F {
+            0x0 => "8XY0",
+            0x1 => "8XY1",
+            0x2 => "8XY2",
+            0x3 => "8XY3",
+            0x4 => "8XY4",
+            0x5 => "8XY5",
+            0x6 => "8XY6",
+            0x7 => "8XY7",
+            0xE => "8XYE",
+            _ => "????",
+        },
+        0x9 if opcode & 0x000F == 0 => "9XY0",
+        0xA => "ANNN",
+        0xB => "BNNN",
+        0xC => "CXNN",
+        0xD => "DXYN",
+        0xE => match opcode & 0x00FF {
+            0x9E => "EX9E",
+            0xA1 => "EXA1",
+            _ => "????",
+        },
+        0xF => match opcode & 0x00FF {
+            0x07 => "FX07",
+            0x0A => "FX0A",
+            0x15 => "FX15",
+            0x18 => "FX18",
+            0x1E => "FX1E",
+            0x29 => "FX29",
+            0x30 => "FX30",
+            0x33 => "FX33",
+            0x55 => "FX55",
+            0x65 => "FX65",
+            0x75 => "FX75",
+            0x85 => "FX85",
+            _ => "????",
+        },
+        _ => "????",
+    }
+}
+
+/// The instruction in the mnemonics of Cowgod's CHIP-8 reference, e.g. `DRW V1, V2, 5`.
+pub fn disassemble(opcode: u16) -> String {
+    let x = (opcode & 0x0F00) >> 8;
+    let y = (opcode & 0x00F0) >> 4;
+    let n = opcode & 0x000F;
+    let nn = opcode & 0x00FF;
+    let nnn = opcode & 0x0FFF;
+    match pattern(opcode) {
+        "00E0" => String::from("CLS"),
+        "00EE" => String::from("RET"),
+        "0NNN" => format!("SYS {:#05X}", nnn),
+        "0010" => String::from("MEGAOFF"),
+        "0011" => String::from("MEGAON"),
+        "00CN" => format!("SCD {}", n),
+        "00FB" => String::from("SCR"),
+        "00FC" => String::from("SCL"),
+        "00FD" => String::from("EXIT"),
+        "00FE" => String::from("LOW"),
+        "00FF" => String::from("HIGH"),
+        "01NN" => format!("LDHI I, {:#04X}", nn),
+        "02NN" => format!("LDPAL {}", nn),
+        "03NN" => format!("SPRW {}", nn),
+        "04NN" => format!("SPRH {}", nn),
+        "05NN" => format!("ALPHA {:#04X}", nn),
+        "060N" => format!("DIGISND {}", n),
+        "0700" => String::from("STOPSND"),
+        "080N" => format!("BMODE {}", n),
+        "09NN" => format!("CCOL {:#04X}", nn),
+        "1NNN" => format!("JP {:#05X}", nnn),
+        "2NNN" => format!("CALL {:#05X}", nnn),
+        "3XNN" => format!("SE V{:X}, {:#04X}", x, nn),
+        "4XNN" => format!("SNE V{:X}, {:#04X}", x, nn),
+        "5XY0" => format!("SE V{:X}, V{:X}", x, y),
+        "6XNN" => format!("LD V{:X}, {:#04X}", x, nn),
+        "7XNN" => format!("ADD V{:X}, {:#04X}", x, nn),
+        "8XY0" => format!("LD V{:X}, V{:X}", x, y),
+        "8XY1" => format!("OR V{:X}, V{:X}", x, y),
+        "8XY2" => format!("AND V{:X}, V{:X}", x, y),
+        "8XY3" => format!("XOR V{:X}, V{:X}", x, y),
+        "8XY4" => format!("ADD V{:X}, V{:X}", x, y),
+        "8XY5" => format!("SUB V{:X}, V{:X}", x, y),
+        "8XY6" => format!("SHR V{:X}, V{:X}", x, y),
+        "8XY7" => format!("SUBN V{:X}, V{:X}", x, y),
+        "8XYE" => format!("SHL V{:X}, V{:X}", x, y),
+        "9XY0" => format!("SNE V{:X}, V{:X}", x, y),
+        "ANNN" => format!("LD I, {:#05X}", nnn),
+        "BNNN" => format!("JP V0, {:#05X}", nnn),
+        "CXNN" => format!("RND V{:X}, {:#04X}", x, nn),
+        "DXYN" => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        "EX9E" => format!("SKP V{:X}", x),
+        "EXA1" => format!("SKNP V{:X}", x),
+        "FX07" => format!("LD V{:X}, DT", x),
+        "FX0A" => format!("LD V{:X}, K", x),
+        "FX15" => format!("LD DT, V{:X}", x),
+        "FX18" => format!("LD ST, V{:X}", x),
+        "FX1E" => format!("ADD I, V{:X}", x),
+        "FX29" => format!("LD F, V{:X}", x),
+        "FX30" => format!("LD HF, V{:X}", x),
+        "FX33" => format!("LD B, V{:X}", x),
+        "FX55" => format!("LD [I], V{:X}", x),
+        "FX65" => format!("LD V{:X}, [I]", x),
+        "FX75" => format!("LD R, V{:X}", x),
+        "FX85" => format!("LD V{:X}, R", x),
+        _ => format!("DW {:#06X}", opcode),
+    }
+}
+
+/// The mnemonic alone, e.g. `DRW`.
+pub fn mnemonic(opcode: u16) -> &'static str {
+    match pattern(opcode) {
+        "00E0" => "CLS",
+        "00EE" => "RET",
+        "0NNN" => "SYS",
+        "0010" => "MEGAOFF",
+        "0011" => "MEGAON",
+        "00CN" => "SCD",
+        "00FB" => "SCR",
+        "00FC" => "SCL",
+        "00FD" => "EXIT",
+        "00FE" => "LOW",
+        "00FF" => "HIGH",
+        "01NN" => "LDHI",
+        "02NN" => "LDPAL",
+        "03NN" => "SPRW",
+        "04NN" => "SPRH",
+        "05NN" => "ALPHA",
+        "060N" => "DIGISND",
+        "0700" => "STOPSND",
+        "080N" => "BMODE",
+        "09NN" => "CCOL",
+        "1NNN" | "BNNN" => "JP",
+        "2NNN" => "CALL",
+        "3XNN" | "5XY0" => "SE",
+        "4XNN" | "9XY0" => "SNE",
+        "6XNN" | "8XY0" | "ANNN" | "FX07" | "FX0A" | "FX15" | "FX18" | "FX29" | "FX30" | "FX33" | "FX55" | "FX65" | "FX75" | "FX85" => "LD",
+        "7XNN" | "8XY4" | "FX1E" => "ADD",
+        "8XY1" => "OR",
+        "8XY2" => "AND",
+        "8XY3" => "XOR",
+        "8XY5" => "SUB",
+        "8XY6" => "SHR",
+        "8XY7" => "SUBN",
+        "8XYE" => "SHL",
+        "CXNN" => "RND",
+        "DXYN" => "DRW",
+        "EX9E" => "SKP",
+        "EXA1" => "SKNP",
+        _ => "DW",
+    }
+}
@@ -0,0 +1,327 @@
+use crate::init::FONT_SET_START_ADDRESS;
+use crate::state::State;
+use clap::ValueEnum;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Hex digit fonts from the interpreters of different machines, as collected by Octo.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum FontSet {
+    #[default]
+    Octo,
+    Vip,
+    Eti660,
+    Dream6800,
+    FishNChips,
+}
+
+impl FontSet {
+    pub const ALL: [FontSet; 5] = [FontSet::Octo, FontSet::Vip, FontSet::Eti660, FontSet::Dream6800, FontSet::FishNChips];
+
+    pub fn glyphs(&self) -> &'static [u8; 80] {
+        match self {
+            FontSet::Octo => &OCTO_FONT,
+            FontSet::Vip => &VIP_FONT,
+            FontSet::Eti660 => &ETI660_FONT,
+            FontSet::Dream6800 => &DREAM6800_FONT,
+            FontSet::FishNChips => &FISH_N_CHIPS_FONT,
+        }
+    }
+}
+
+impl fmt::Display for FontSet {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontSet::Octo => write!(f, "Octo"),
+            FontSet::Vip => write!(f, "COSMAC VIP"),
+            FontSet::Eti660 => write!(f, "ETI-660"),
+            FontSet::Dream6800 => write!(f, "DREAM 6800"),
+            FontSet::FishNChips => write!(f, "FISH 'N' CHIPS"),
+        }
+    }
+}
+
+/// The small font loaded below the program: one of the built-in sets or a file of 16 glyphs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Font {
+    Builtin(FontSet),
+    Custom { path: PathBuf, glyphs: Vec<u8> },
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Io(io::Error),
+    InvalidSize(usize),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FontError::Io(error) => write!(f, "could not read font: {}", error),
+            FontError::InvalidSize(size) => write!(f, "font is {} bytes; expected 16 glyphs of 1 to 10 bytes each", size),
+        }
+    }
+}
+
+impl Default for Font {
+    fn default() -> Self {
+        Font::Builtin(FontSet::default())
+    }
+}
+
+/// Where the fonts ended up in memory, for FX29 and FX30.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FontLayout {
+    /// Bytes per small glyph.
+    pub small_height: u16,
+    pub big_start: u16,
+}
+
+impl FontLayout {
+    /// First address after the big font.
+    pub fn end(&self) -> usize {
+        self.big_start as usize + BIG_FONT.len()
+    }
+}
+
+impl Default for FontLayout {
+    fn default() -> Self {
+        Self {
+            small_height: 5,
+            big_start: (FONT_SET_START_ADDRESS + 80) as u16,
+        }
+    }
+}
+
+impl Font {
+    /// Reads a raw font binary of 16 glyphs, all the same height.
+    pub fn from_file(path: &Path) -> Result<Font, FontError> {
+        let glyphs = fs::read(path).map_err(FontError::Io)?;
+        if glyphs.is_empty() || glyphs.len() % 16 != 0 || glyphs.len() > 160 {
+            return Err(FontError::InvalidSize(glyphs.len()));
+        }
+        Ok(Font::Custom { path: path.to_path_buf(), glyphs })
+    }
+
+    fn glyphs(&self) -> &[u8] {
+        match self {
+            Font::Builtin(font_set) => font_set.glyphs(),
+            Font::Custom { glyphs, .. } => glyphs,
+        }
+    }
+
+    /// Writes the small font and then the SCHIP big font into memory and points FX29 and FX30 at them.
+    pub fn load_into(&self, state: &mut State) {
+        let glyphs = self.glyphs();
+        let big_start = FONT_SET_START_ADDRESS + glyphs.len();
+        state.memory[FONT_SET_START_ADDRESS..big_start].copy_from_slice(glyphs);
+        state.memory[big_start..big_start + BIG_FONT.len()].copy_from_slice(&BIG_FONT);
+        state.font = FontLayout {
+            small_height: (glyphs.len() / 16) as u16,
+            big_start: big_start as u16,
+        };
+    }
+}
+
+impl fmt::Display for Font {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Font::Builtin(font_set) => write!(f, "{}", font_set),
+            Font::Custom { path, .. } => write!(f, "{}", path.file_name().unwrap_or_default().to_string_lossy()),
+        }
+    }
+}
+
+static OCTO_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+static VIP_FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x60, 0x20, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0xA0, 0xA0, 0xF0, 0x20, 0x20, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x10, 0x10, 0x10, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xF0, 0x50, 0x70, 0x50, 0xF0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xF0, 0x50, 0x50, 0x50, 0xF0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+static ETI660_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x20, 0x20, 0x20, 0x20, 0x20, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0xA0, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0x80, 0x80, 0xE0, 0xA0, 0xE0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0x20, 0x20, 0xE0, 0xA0, 0xE0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+static DREAM6800_FONT: [u8; 80] = [
+    0xE0, 0xA0, 0xA0, 0xA0, 0xE0, // 0
+    0x40, 0x40, 0x40, 0x40, 0x40, // 1
+    0xE0, 0x20, 0xE0, 0x80, 0xE0, // 2
+    0xE0, 0x20, 0xE0, 0x20, 0xE0, // 3
+    0x80, 0xA0, 0xA0, 0xE0, 0x20, // 4
+    0xE0, 0x80, 0xE0, 0x20, 0xE0, // 5
+    0xE0, 0x80, 0xE0, 0xA0, 0xE0, // 6
+    0xE0, 0x20, 0x20, 0x20, 0x20, // 7
+    0xE0, 0xA0, 0xE0, 0xA0, 0xE0, // 8
+    0xE0, 0xA0, 0xE0, 0x20, 0xE0, // 9
+    0xE0, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xE0, 0xA0, 0xC0, // B
+    0xE0, 0x80, 0x80, 0x80, 0xE0, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xE0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+static FISH_N_CHIPS_FONT: [u8; 80] = [
+    0x60, 0xA0, 0xA0, 0xA0, 0xC0, // 0
+    0x40, 0xC0, 0x40, 0x40, 0xE0, // 1
+    0xC0, 0x20, 0x40, 0x80, 0xE0, // 2
+    0xC0, 0x20, 0x40, 0x20, 0xC0, // 3
+    0x20, 0xA0, 0xE0, 0x20, 0x20, // 4
+    0xE0, 0x80, 0xC0, 0x20, 0xC0, // 5
+    0x40, 0x80, 0xC0, 0xA0, 0x40, // 6
+    0xE0, 0x20, 0x60, 0x40, 0x40, // 7
+    0x40, 0xA0, 0x40, 0xA0, 0x40, // 8
+    0x40, 0xA0, 0x60, 0x20, 0x40, // 9
+    0x40, 0xA0, 0xE0, 0xA0, 0xA0, // A
+    0xC0, 0xA0, 0xC0, 0xA0, 0xC0, // B
+    0x60, 0x80, 0x80, 0x80, 0x60, // C
+    0xC0, 0xA0, 0xA0, 0xA0, 0xC0, // D
+    0xE0, 0x80, 0xC0, 0x80, 0xE0, // E
+    0xE0, 0x80, 0xC0, 0x80, 0x80, // F
+];
+
+/// SCHIP's 8x10 digits for FX30, with Octo's A to F.
+static BIG_FONT: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init;
+    use crate::platform::PlatformName;
+
+    fn loaded(font: &Font) -> State {
+        let platform = PlatformName::Chip8.platform();
+        init::init_state(&[0x00, 0xE0], platform, platform.quirks, font).unwrap()
+    }
+
+    #[test]
+    fn builtin_sets_load_below_the_big_font() {
+        for font_set in FontSet::ALL {
+            let state = loaded(&Font::Builtin(font_set));
+            assert_eq!(state.font, FontLayout::default(), "{}", font_set);
+            assert_eq!(state.memory[FONT_SET_START_ADDRESS..FONT_SET_START_ADDRESS + 80], font_set.glyphs()[..], "{}", font_set);
+            assert_eq!(state.memory[state.font.big_start as usize..state.font.end()], BIG_FONT, "{}", font_set);
+            assert!(state.font.end() <= 0x200, "{}", font_set);
+            // Every glyph is 4 pixels wide at most, so digits drawn side by side don't touch
+            assert!(font_set.glyphs().iter().all(|row| row & 0x0F == 0), "{}", font_set);
+        }
+    }
+
+    #[test]
+    fn builtin_sets_are_distinct() {
+        for (i, a) in FontSet::ALL.iter().enumerate() {
+            for b in &FontSet::ALL[i + 1..] {
+                assert_ne!(a.glyphs(), b.glyphs(), "{} and {}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn font_files_set_the_glyph_height_and_move_the_big_font() {
+        let path = std::env::temp_dir().join(format!("chip8-font-{}.bin", std::process::id()));
+        let glyphs: Vec<u8> = (0..160).map(|byte| byte as u8).collect();
+        fs::write(&path, &glyphs).unwrap();
+        let font = Font::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        let font = font.unwrap();
+        assert_eq!(font.to_string(), path.file_name().unwrap().to_string_lossy());
+        let state = loaded(&font);
+        assert_eq!(
+            state.font,
+            FontLayout {
+                small_height: 10,
+                big_start: 0x50 + 160
+            }
+        );
+        assert_eq!(state.memory[0x50..0x50 + 160], glyphs[..]);
+        assert_eq!(state.memory[0x50 + 160..0x50 + 320], BIG_FONT);
+    }
+
+    #[test]
+    fn font_files_need_16_glyphs_of_1_to_10_bytes() {
+        let path = std::env::temp_dir().join(format!("chip8-bad-font-{}.bin", std::process::id()));
+        for size in [0, 15, 17, 176] {
+            fs::write(&path, vec![0; size]).unwrap();
+            assert!(matches!(Font::from_file(&path), Err(FontError::InvalidSize(found)) if found == size), "{} bytes", size);
+        }
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(Font::from_file(&path), Err(FontError::Io(_))));
+    }
+
+    #[test]
+    fn fx29_and_fx30_point_at_the_glyph_for_the_low_nibble() {
+        let mut state = loaded(&Font::Builtin(FontSet::Vip));
+        state.registers[1] = 0x1A;
+        crate::opcodes::op_FX29(&mut state, 0xF129);
+        assert_eq!(state.index, 0x50 + 5 * 0xA);
+        crate::opcodes::op_FX30(&mut state, 0xF130);
+        assert_eq!(state.index, state.font.big_start as u32 + 10 * 0xA);
+    }
+}
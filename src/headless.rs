@@ -0,0 +1,519 @@
+//! Running ROMs without a window, for batch runs and differential testing.
+//!
+//! Input is replayed from an input log with one event per line:
+//!
+//! ```text
+//! # frame key state
+//! 120 5 down
+//! 126 5 up
+//! ```
+//!
+//! The frame is a decimal count of 60 Hz frames since the ROM started, the key is the keypad hex
+//! digit, or `2:` and the digit for CHIP-8X's second keypad, and the state is `down` or `up`.
+//! Events are applied before the frame's instructions run. Blank lines and anything after `#` are
+//! ignored.
+
+use crate::capture;
+use crate::capture::GifRecorder;
+use crate::cli;
+use crate::cli::CoverageArgs;
+use crate::cli::CoverageFormat;
+use crate::cli::HeadlessArgs;
+use crate::cli::ProfileArgs;
+use crate::cli::RunArgs;
+use crate::cli::TraceDiffArgs;
+use crate::coverage::Coverage;
+use crate::cpu::CpuFault;
+use crate::cpu::Halt;
+use crate::cpu::Hooks;
+use crate::init;
+use crate::machine::Machine;
+use crate::platform::PlatformName;
+use crate::profiler::Profiler;
+use crate::rotation::Rotation;
+use crate::strict::StrictMode;
+use crate::trace;
+use crate::trace::TraceFilter;
+use crate::trace::Tracer;
+use crate::vip::Vip;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::ops::Range;
+use std::path::Path;
+
+static TICKS_PER_SECOND: u8 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InputEvent {
+    pub frame: u32,
+    /// Keypad key, or 0x10 to 0x1F for the second keypad, as in a keymap.
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Keypad events to replay, sorted by frame.
+#[derive(Debug, Clone, Default)]
+pub struct InputLog {
+    events: Vec<InputEvent>,
+}
+
+#[derive(Debug)]
+pub enum InputLogError {
+    Io(io::Error),
+    InvalidLine { line_number: usize, line: String },
+}
+
+impl fmt::Display for InputLogError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InputLogError::Io(error) => write!(f, "could not read input log: {}", error),
+            InputLogError::InvalidLine { line_number, line } => write!(
+                f,
+                "invalid input log line {}: {:?} (expected `<frame> <hex key> down|up` or `<frame> 2:<hex key> down|up`)",
+                line_number, line
+            ),
+        }
+    }
+}
+
+impl InputLog {
+    pub fn from_file(path: &Path) -> Result<Self, InputLogError> {
+        let contents = fs::read_to_string(path).map_err(InputLogError::Io)?;
+        let mut events = Vec::new();
+        for (line_index, line) in contents.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let event = parse_event(entry).ok_or_else(|| InputLogError::InvalidLine {
+                line_number: line_index + 1,
+                line: line.to_string(),
+            })?;
+            events.push(event);
+        }
+        events.sort_by_key(|event| event.frame);
+        Ok(InputLog { events })
+    }
+
+    /// Whether any events are still to come at or after `frame`.
+    fn has_events_from(&self, frame: u32) -> bool {
+        self.events.last().is_some_and(|event| event.frame >= frame)
+    }
+
+    fn uses_second_keypad(&self) -> bool {
+        self.events.iter().any(|event| event.key >= 16)
+    }
+
+    fn apply(&self, frame: u32, machine: &mut dyn Machine) {
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            let keypad = if event.key < 16 { Some(machine.keypad()) } else { machine.second_keypad() };
+            match keypad {
+                Some(keypad) if event.pressed => keypad.press(event.key),
+                Some(keypad) => keypad.release(event.key),
+                None => {}
+            }
+        }
+    }
+}
+
+fn parse_event(entry: &str) -> Option<InputEvent> {
+    let mut fields = entry.split_whitespace();
+    let frame = fields.next()?.parse().ok()?;
+    let key = fields.next()?;
+    let (key, first_key) = match key.strip_prefix("2:") {
+        Some(key) => (key, 16),
+        None => (key, 0),
+    };
+    let key = u8::from_str_radix(key, 16).ok().filter(|key| *key < 16)? + first_key;
+    let pressed = match fields.next()? {
+        "down" => true,
+        "up" => false,
+        _ => return None,
+    };
+    fields.next().is_none().then_some(InputEvent { frame, key, pressed })
+}
+
+/// How a headless run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    FrameLimit,
+    Halted(Halt),
+    Fault(CpuFault),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Stop::FrameLimit => write!(f, "reached the frame limit"),
+            Stop::Halted(halt) => write!(f, "halted: {}", halt),
+            Stop::Fault(fault) => write!(f, "{}", fault),
+        }
+    }
+}
+
+/// A machine driven frame by frame from an input log instead of the keyboard.
+#[derive(Debug)]
+pub struct Headless {
+    pub machine: Box<dyn Machine>,
+    pub cycles_per_tick: u16,
+    /// Where the loaded program sits in memory.
+    pub program: Range<usize>,
+    /// The display's size in low-res pixels, as from `Platform::window_size`.
+    pub display_size: (usize, usize),
+    inputs: InputLog,
+    frame: u32,
+}
+
+impl Headless {
+    pub fn new(machine: Box<dyn Machine>, cycles_per_tick: u16, program: Range<usize>, display_size: (usize, usize), inputs: InputLog) -> Self {
+        Self {
+            machine,
+            cycles_per_tick,
+            program,
+            display_size,
+            inputs,
+            frame: 0,
+        }
+    }
+
+    /// Loads the ROM, its Octo options and the input log, and seeds the random number generator.
+    /// The hooks come back with strict mode set up if it was asked for.
+    pub fn load(args: &HeadlessArgs) -> Result<(Self, Hooks), String> {
+        let inputs = match &args.input {
+            Some(path) => InputLog::from_file(path).map_err(|error| format!("{}: {}", path.display(), error))?,
+            None => InputLog::default(),
+        };
+        let rom = init::read_rom(&args.rom).map_err(|error| format!("{}: {}", args.rom.display(), error))?;
+        let platform = args.machine.platform(&args.rom, &rom);
+        if let Some(path) = args.input.as_ref().filter(|_| inputs.uses_second_keypad() && platform.name != PlatformName::Chip8X) {
+            return Err(format!("{}: keys on a second keypad need the {} platform", path.display(), PlatformName::Chip8X));
+        }
+        if let Some(images) = args.machine.vip_images().map_err(|error| error.to_string())? {
+            if args.strict.strict {
+                return Err(String::from("strict mode checks CHIP-8 instructions, which the emulated COSMAC VIP doesn't expose"));
+            }
+            let vip = Vip::new(&images, &rom.bytes, &platform).map_err(|error| format!("{}: {}", args.rom.display(), error))?;
+            let program_start = platform.load_address as usize;
+            let program = program_start..program_start + rom.bytes.len();
+            return Ok((Self::new(Box::new(vip), platform.cycles_per_tick, program, platform.window_size(), inputs), Hooks::default()));
+        }
+        let font = args.machine.font(&platform).map_err(|error| match &args.machine.font_file {
+            Some(path) => format!("{}: {}", path.display(), error),
+            None => error.to_string(),
+        })?;
+
+        let mut quirks = platform.quirks;
+        let mut cycles_per_tick = platform.cycles_per_tick;
+        if let Some(options) = &rom.options {
+            options.apply_quirks(&mut quirks);
+            cycles_per_tick = options.cycles_per_tick().unwrap_or(cycles_per_tick);
+        }
+        args.machine.apply_quirks(&mut quirks);
+        if let Some(ips) = args.ips {
+            cycles_per_tick = cli::cycles_per_tick(ips, TICKS_PER_SECOND);
+        }
+        let mut state = init::init_state(&rom.bytes, platform, quirks, &font).map_err(|error| format!("{}: {}", args.rom.display(), error))?;
+        state.rng = args.seed;
+        state.stack_policy = args.machine.stack_policy;
+        let hooks = Hooks {
+            strict: args.strict.strict.then(|| StrictMode::new(&state, rom.bytes.len(), args.strict.strict_pause)),
+            ..Hooks::default()
+        };
+        let program_start = platform.load_address as usize;
+        let program = program_start..program_start + rom.bytes.len();
+        Ok((Self::new(Box::new(state), cycles_per_tick, program, platform.window_size(), inputs), hooks))
+    }
+
+    /// Runs one frame, printing any warnings to standard error.
+    pub fn run_frame(&mut self, hooks: &mut Hooks) -> Result<(), CpuFault> {
+        self.inputs.apply(self.frame, self.machine.as_mut());
+        self.frame += 1;
+        let result = self.machine.run_frame(self.cycles_per_tick, hooks);
+        for warning in self.machine.take_warnings() {
+            eprintln!("Warning in frame {}: {}", self.frame, warning);
+        }
+        result
+    }
+
+    /// Runs until `frames` frames have passed, the CPU faults, or the program halts with nothing
+    /// left in the input log to wake it. `on_frame` sees the machine after every frame.
+    pub fn run(&mut self, hooks: &mut Hooks, frames: u32, mut on_frame: impl FnMut(&dyn Machine)) -> Stop {
+        while self.frame < frames {
+            if let Err(fault) = self.run_frame(hooks) {
+                return Stop::Fault(fault);
+            }
+            on_frame(self.machine.as_ref());
+            match self.machine.halted() {
+                Some(halt @ (Halt::SelfJump { .. } | Halt::Stopped { .. })) => return Stop::Halted(halt),
+                Some(halt @ Halt::WaitingForKey { .. }) if !self.inputs.has_events_from(self.frame) => return Stop::Halted(halt),
+                _ => {}
+            }
+        }
+        Stop::FrameLimit
+    }
+
+    /// Number of frames run so far.
+    pub fn frame(&self) -> u32 {
+        self.frame
+    }
+}
+
+/// Loads a ROM for a command that watches CHIP-8 instructions, which only the high-level
+/// interpreter runs.
+fn load_interpreted(args: &HeadlessArgs) -> Result<(Headless, Hooks), String> {
+    if args.machine.vip_monitor.is_some() {
+        return Err(String::from("the emulated COSMAC VIP runs the interpreter's 1802 code, so this needs the high-level interpreter"));
+    }
+    Headless::load(args)
+}
+
+/// Runs `chip8 profile`, printing the report even if the ROM faults partway through.
+pub fn profile(args: &ProfileArgs) -> i32 {
+    let (mut machine, mut hooks) = match load_interpreted(&args.run) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    hooks.profiler = Some(Profiler::default());
+    let stop = machine.run(&mut hooks, args.frames, |_| {});
+    if stop != Stop::FrameLimit {
+        println!("Stopped after {} frames: {}\n", machine.frame(), stop);
+    }
+    if let Some(profiler) = &hooks.profiler {
+        print!("{}", profiler.report(args.top));
+    }
+    exit_code(stop)
+}
+
+/// Runs `chip8 coverage`, writing the report even if the ROM faults partway through.
+pub fn coverage(args: &CoverageArgs) -> i32 {
+    let (mut machine, mut hooks) = match load_interpreted(&args.run) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    hooks.coverage = Some(Coverage::new(machine.machine.memory().len()));
+    let stop = machine.run(&mut hooks, args.frames, |_| {});
+    if stop != Stop::FrameLimit {
+        eprintln!("Stopped after {} frames: {}", machine.frame(), stop);
+    }
+    let Some(coverage) = &hooks.coverage else {
+        return exit_code(stop);
+    };
+    let report = match args.format {
+        CoverageFormat::Listing => coverage.listing(machine.machine.memory(), machine.program.clone()),
+        CoverageFormat::Json => coverage.to_json(),
+    };
+    match &args.output {
+        Some(path) => {
+            if let Err(error) = fs::write(path, report) {
+                eprintln!("Could not write {}: {}", path.display(), error);
+                return 2;
+            }
+        }
+        None => print!("{}", report),
+    }
+    exit_code(stop)
+}
+
+/// Runs `chip8 run`, saving the final screen and a recording if asked to.
+pub fn run_rom(args: &RunArgs) -> i32 {
+    let (mut machine, mut hooks) = match Headless::load(&args.run) {
+        Ok(loaded) => loaded,
+        Err(error) => {
+            eprintln!("{}", error);
+            return 2;
+        }
+    };
+    let mut recorder = match &args.record {
+        Some(path) => match GifRecorder::create(path, machine.display_size, args.scale, args.palette, Rotation::None, TICKS_PER_SECOND as f64) {
+            Ok(recorder) => Some(recorder),
+            Err(error) => {
+                eprintln!("Could not record to {}: {}", path.display(), error);
+                return 2;
+            }
+        },
+        None => None,
+    };
+    let mut capture_error = None;
+    let stop = machine.run(&mut hooks, args.frames, |emulated| {
+        let recorded = recorder.as_mut().map(|recorder| match emulated.colour_video() {
+            Some(frame) => recorder.push_colour_frame(&frame),
+            None => recorder.push_frame(emulated.video(), emulated.display_width()),
+        });
+        if let Some(Err(error)) = recorded {
+            capture_error.get_or_insert(error);
+            recorder = None;
+        }
+    });
+    println!("Stopped after {} frames: {}", machine.frame(), stop);
+
+    if let Some(path) = &args.screenshot {
+        let saved = match machine.machine.colour_video() {
+            Some(frame) => capture::save_colour_screenshot(path, &frame, args.scale, Rotation::None),
+            None => capture::save_screenshot(path, machine.machine.video(), machine.machine.display_width(), args.scale, args.palette, Rotation::None),
+        };
+        if let Err(error) = saved {
+            capture_error.get_or_insert(error);
+        }
+    }
+    if let Some(error) = capture_error {
+        eprintln!("Capture failed: {}", error);
+        return 2;
+    }
+    exit_code(stop)
+}
+
+/// 1 if the ROM faulted, otherwise 0; a halt is the normal way for a test ROM to finish.
+fn exit_code(stop: Stop) -> i32 {
+    match stop {
+        Stop::Fault(_) => 1,
+        Stop::FrameLimit | Stop::Halted(_) => 0,
+    }
+}
+
+/// Runs `chip8 trace-diff` and returns the process exit code: 0 when the traces match, 1 when
+/// they differ and 2 when something could not be read.
+pub fn trace_diff(args: &TraceDiffArgs) -> i32 {
+    match run_trace_diff(args) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(error) => {
+            eprintln!("{}", error);
+            2
+        }
+    }
+}
+
+fn run_trace_diff(args: &TraceDiffArgs) -> Result<bool, String> {
+    let reference = trace::read_trace(&args.reference, args.format).map_err(|error| format!("{}: {}", args.reference.display(), error))?;
+    let (mut machine, mut hooks) = load_interpreted(&args.run)?;
+
+    // However many instructions a frame runs, only the ones the reference covers are kept
+    hooks.tracer = Some(Tracer::first_entries(&args.output, args.format, TraceFilter::default(), reference.len()));
+    let mut fault = None;
+    while machine.frame() < args.max_frames && hooks.tracer.as_ref().is_some_and(|tracer| tracer.recorded() < reference.len()) {
+        if let Err(error) = machine.run_frame(&mut hooks) {
+            fault = Some(error);
+            break;
+        }
+    }
+
+    let tracer = hooks.tracer.as_mut().expect("trace-diff always traces");
+    tracer.dump().map_err(|error| format!("{}: {}", tracer.path().display(), error))?;
+    let ours = tracer.entries();
+    if let Some(fault) = fault {
+        println!("Stopped after {} instructions: {}", ours.len(), fault);
+    }
+
+    let Some(index) = trace::first_difference(&ours, &reference) else {
+        println!("Traces match for all {} instructions", reference.len());
+        return Ok(true);
+    };
+    match (ours.get(index), reference.get(index)) {
+        (Some(ours), Some(theirs)) => {
+            println!("Traces differ at instruction {} (PC={:04X} OP={:04X} in the reference):", index + 1, theirs.pc, theirs.opcode);
+            for (field, ours, theirs) in ours.differences(theirs) {
+                println!("  {}: expected {}, got {}", field, theirs, ours);
+            }
+        }
+        (None, _) => println!("Our trace ends after {} instructions but the reference has {}", ours.len(), reference.len()),
+        (_, None) => println!("The reference ends after {} instructions", reference.len()),
+    }
+    if let Some(previous) = index.checked_sub(1).and_then(|previous| reference.get(previous)) {
+        println!("Previous instruction: {}", previous);
+    }
+    Ok(false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Args;
+    use crate::cli::Command;
+    use clap::Parser;
+
+    fn trace_diff_args(reference: &str, output: &Path, extra: &[&str]) -> TraceDiffArgs {
+        let fixtures = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures");
+        let mut args = vec![
+            String::from("chip8"),
+            String::from("trace-diff"),
+            format!("{}/trace.ch8", fixtures),
+            format!("{}/{}", fixtures, reference),
+            String::from("--output"),
+            output.display().to_string(),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        let args = Args::parse_from(args);
+        match args.command {
+            Some(Command::TraceDiff(trace_diff)) => trace_diff,
+            command => panic!("parsed as {:?}", command),
+        }
+    }
+
+    #[test]
+    fn parses_input_events_for_either_keypad() {
+        assert_eq!(parse_event("120 a down"), Some(InputEvent { frame: 120, key: 0x0A, pressed: true }));
+        assert_eq!(
+            parse_event("126 2:A up"),
+            Some(InputEvent {
+                frame: 126,
+                key: 0x1A,
+                pressed: false
+            })
+        );
+        for entry in ["1 2:10 down", "1 3:1 down", "1 10 down", "1 a held", "1 a down now"] {
+            assert_eq!(parse_event(entry), None, "{:?}", entry);
+        }
+    }
+
+    #[test]
+    fn replays_second_keypad_events_on_chip8x() {
+        let platform = PlatformName::Chip8X.platform();
+        let font = crate::font::Font::Builtin(crate::font::FontSet::Vip);
+        let mut state = init::init_state(&[0x00, 0xE0], platform, platform.quirks, &font).unwrap();
+        let inputs = InputLog {
+            events: vec![InputEvent { frame: 0, key: 0x03, pressed: true }, InputEvent { frame: 0, key: 0x1A, pressed: true }],
+        };
+        inputs.apply(0, &mut state);
+        assert!(state.keypad.is_held(0x3));
+        assert!(state.keypad2.is_held(0xA));
+        assert!(!state.keypad.is_held(0xA));
+    }
+
+    #[test]
+    fn trace_diff_matches_its_own_trace() {
+        let output = std::env::temp_dir().join(format!("chip8-trace-diff-match-{}.log", std::process::id()));
+        let matched = run_trace_diff(&trace_diff_args("trace.txt", &output, &[]));
+        let written = trace::read_trace(&output, trace::TraceFormat::Text);
+        fs::remove_file(&output).unwrap();
+        assert_eq!(matched, Ok(true));
+        let reference = trace::read_trace(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/trace.txt")), trace::TraceFormat::Text).unwrap();
+        assert_eq!(written.unwrap(), reference);
+    }
+
+    #[test]
+    fn trace_diff_keeps_the_first_instructions_under_vip_timing() {
+        // A VIP-timed frame runs far more instructions than the reference holds
+        let output = std::env::temp_dir().join(format!("chip8-trace-diff-vip-{}.log", std::process::id()));
+        let matched = run_trace_diff(&trace_diff_args("trace.txt", &output, &["--platform", "vip"]));
+        let written = trace::read_trace(&output, trace::TraceFormat::Text);
+        fs::remove_file(&output).unwrap();
+        assert_eq!(matched, Ok(true));
+        let reference = trace::read_trace(Path::new(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/trace.txt")), trace::TraceFormat::Text).unwrap();
+        assert_eq!(written.unwrap(), reference);
+    }
+
+    #[test]
+    fn trace_diff_reports_a_diverged_trace() {
+        let output = std::env::temp_dir().join(format!("chip8-trace-diff-diverged-{}.log", std::process::id()));
+        let matched = run_trace_diff(&trace_diff_args("trace-diverged.txt", &output, &[]));
+        fs::remove_file(&output).unwrap();
+        assert_eq!(matched, Ok(false));
+    }
+}
@@ -1,49 +1,238 @@
+use crate::cartridge;
+use crate::chip8x::ColourBoard;
+use crate::font::Font;
+use crate::font::FontLayout;
+use crate::megachip::MegaChip;
+use crate::octo;
+use crate::octo::OctoOptions;
+use crate::platform::Platform;
+use crate::platform::PlatformName;
+use crate::state::Keypad;
+use crate::state::Quirks;
+use crate::state::StackPolicy;
 use crate::state::State;
+use std::fmt;
 use std::fs;
+use std::fs::File;
+use std::io;
 use std::io::Read;
 use std::path::Path;
 
-pub static PROGRAM_START_ADDRESS: u16 = 0x200;
 pub static FONT_SET_START_ADDRESS: usize = 0x50;
 
-static FONT_SET: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
-
-pub fn init_state(rom_path: &Path) -> State {
+/// File extensions offered by the file dialog and accepted when a file is dropped on the window.
+pub static ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "mc8", "gif"];
+
+/// Octo's size limit for CHIP-8 programs, above which a ROM can only be meant for XO-CHIP.
+static MAX_CHIP8_ROM_SIZE: usize = 3584;
+
+#[derive(Debug)]
+pub enum RomError {
+    NotFound,
+    UnsupportedFormat(&'static str),
+    InvalidCartridge(String),
+    InvalidOptions(String),
+    Empty,
+    TooLarge { size: usize, max_size: usize },
+    NotForVip(PlatformName),
+    Unreadable(io::Error),
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::NotFound => write!(f, "ROM file not found"),
+            RomError::UnsupportedFormat(reason) => write!(f, "unsupported ROM format: {}", reason),
+            RomError::InvalidCartridge(reason) => write!(f, "invalid Octo cartridge: {}", reason),
+            RomError::InvalidOptions(reason) => write!(f, "invalid Octo options file: {}", reason),
+            RomError::Empty => write!(f, "ROM is empty"),
+            RomError::TooLarge { size, max_size } => write!(f, "ROM is {} bytes but at most {} bytes fit in memory", size, max_size),
+            RomError::NotForVip(platform) => write!(f, "{} programs need more than the emulated COSMAC VIP has", platform),
+            RomError::Unreadable(error) => write!(f, "could not read ROM: {}", error),
+        }
+    }
+}
+
+/// A program read from disk, with any Octo options that came with it.
+#[derive(Debug)]
+pub struct Rom {
+    pub bytes: Vec<u8>,
+    pub options: Option<OctoOptions>,
+}
+
+/// Reads a whole ROM file into memory, unpacking Octo cartridges and picking up an Octo options
+/// file sitting next to a plain ROM.
+pub fn read_rom(rom_path: &Path) -> Result<Rom, RomError> {
+    let rom = read_rom_file(rom_path)?;
+    if let Some(max_size) = rom.options.as_ref().and_then(|options| options.max_size) {
+        if max_size > MAX_CHIP8_ROM_SIZE {
+            return Err(RomError::UnsupportedFormat("XO-CHIP programs are not emulated"));
+        }
+        if rom.bytes.len() > max_size {
+            return Err(RomError::TooLarge { size: rom.bytes.len(), max_size });
+        }
+    }
+    Ok(rom)
+}
+
+fn read_rom_file(rom_path: &Path) -> Result<Rom, RomError> {
+    let file_extension = extension(rom_path);
+    // Octo source is never offered or accepted on drop, but can still be named on the command line
+    if file_extension.as_deref() == Some("8o") {
+        return Err(RomError::UnsupportedFormat("Octo source has to be assembled with Octo first"));
+    }
+    if file_extension.as_deref() == Some("xo8") {
+        return Err(RomError::UnsupportedFormat("XO-CHIP programs are not emulated"));
+    }
+    let mut file = File::open(rom_path).map_err(|error| match error.kind() {
+        io::ErrorKind::NotFound => RomError::NotFound,
+        _ => RomError::Unreadable(error),
+    })?;
+    let mut rom = Vec::new();
+    file.read_to_end(&mut rom).map_err(RomError::Unreadable)?;
+    if file_extension.as_deref() == Some("gif") {
+        let cartridge = cartridge::decode(&rom)?;
+        return Ok(Rom {
+            bytes: cartridge.rom,
+            options: Some(cartridge.options),
+        });
+    }
+    Ok(Rom {
+        bytes: rom,
+        options: read_sidecar_options(rom_path)?,
+    })
+}
+
+fn read_sidecar_options(rom_path: &Path) -> Result<Option<OctoOptions>, RomError> {
+    for options_path in octo::sidecar_paths(rom_path) {
+        if let Ok(json) = fs::read_to_string(&options_path) {
+            let options = serde_json::from_str(&json).map_err(|error| RomError::InvalidOptions(format!("{}: {}", options_path.display(), error)))?;
+            return Ok(Some(options));
+        }
+    }
+    Ok(None)
+}
+
+/// Whether `path` has one of the `ROM_EXTENSIONS`, ignoring case.
+pub fn has_rom_extension(path: &Path) -> bool {
+    extension(path).is_some_and(|extension| ROM_EXTENSIONS.contains(&extension.as_str()))
+}
+
+fn extension(path: &Path) -> Option<String> {
+    path.extension().map(|extension| extension.to_string_lossy().to_lowercase())
+}
+
+/// Builds a fresh `platform` machine with `font` and `rom` loaded.
+pub fn init_state(rom: &[u8], platform: Platform, quirks: Quirks, font: &Font) -> Result<State, RomError> {
+    let max_size = platform.memory_size - platform.load_address as usize;
+    if rom.is_empty() {
+        return Err(RomError::Empty);
+    }
+    if rom.len() > max_size {
+        return Err(RomError::TooLarge { size: rom.len(), max_size });
+    }
+
     let mut state = State {
         registers: [0; 16],
-        memory: [0; 4096],
-        stack: [0; 16],
-        keypad: [false; 16],
-        video: [0; 32],
+        memory: vec![0; platform.memory_size],
+        stack: Vec::new(),
+        keypad: Keypad::default(),
+        keypad2: Keypad::default(),
+        video: vec![0; platform.display_height],
         index: 0,
-        pc: PROGRAM_START_ADDRESS,
+        pc: platform.start_address,
         sp: 0,
         delay_timer: 0,
         sound_timer: 0,
+        quirks,
+        fault: None,
+        warnings: Vec::new(),
+        halted: None,
+        stack_policy: StackPolicy::default(),
+        rng: rand::random(),
+        font: FontLayout::default(),
+        platform,
+        cycle_debt: 0,
+        mega: (platform.name == PlatformName::MegaChip).then(MegaChip::default),
+        colour_board: (platform.name == PlatformName::Chip8X).then(|| ColourBoard::new(platform.display_height)),
+        io_port: 0,
+        delay_wait: false,
+        key_waiting: false,
+        hires: false,
+        flags: [0; 8],
     };
-    state.memory[FONT_SET_START_ADDRESS..FONT_SET_START_ADDRESS + 80].clone_from_slice(&FONT_SET);
-    load_rom(&mut state, rom_path);
-    state
+    font.load_into(&mut state);
+    let program_start = platform.load_address as usize;
+    state.memory[program_start..program_start + rom.len()].copy_from_slice(rom);
+    Ok(state)
 }
 
-fn load_rom(state: &mut State, rom_path: &Path) {
-    let mut f = fs::File::open(rom_path).expect("no file found");
-    f.read(&mut state.memory[PROGRAM_START_ADDRESS as usize..4096]).expect("rom too large");
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes `files` to a fresh directory and reads the ROM named by the first.
+    fn read(test: &str, files: &[(&str, &[u8])]) -> Result<Rom, RomError> {
+        let directory = std::env::temp_dir().join(format!("chip8-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        for (name, contents) in files {
+            fs::write(directory.join(name), contents).unwrap();
+        }
+        let read = read_rom(&directory.join(files[0].0));
+        fs::remove_dir_all(&directory).unwrap();
+        read
+    }
+
+    #[test]
+    fn refuses_xo_chip_programs() {
+        let by_extension = read("xo8", &[("game.xo8", &[0x00, 0xE0])]);
+        assert!(matches!(by_extension, Err(RomError::UnsupportedFormat(_))), "{:?}", by_extension);
+        let by_options = read("xo-options", &[("game.ch8", &[0x00, 0xE0]), ("game.json", br#"{"maxSize": 65024}"#)]);
+        assert!(matches!(by_options, Err(RomError::UnsupportedFormat(_))), "{:?}", by_options);
+        let chip8 = read("chip8-options", &[("game.ch8", &[0x00, 0xE0]), ("game.json", br#"{"maxSize": 3584}"#)]);
+        assert_eq!(chip8.unwrap().bytes, [0x00, 0xE0]);
+    }
+
+    fn init(rom: &[u8], name: PlatformName) -> Result<State, RomError> {
+        let platform = name.platform();
+        init_state(rom, platform, platform.quirks, &Font::default())
+    }
+
+    #[test]
+    fn refuses_an_empty_rom() {
+        assert!(matches!(init(&[], PlatformName::Chip8), Err(RomError::Empty)));
+    }
+
+    #[test]
+    fn refuses_a_rom_that_does_not_fit_in_memory() {
+        let rom = vec![0; 0x1000 - 0x200];
+        assert!(init(&rom, PlatformName::Chip8).is_ok());
+        let rom = vec![0; 0x1000 - 0x200 + 1];
+        assert!(matches!(init(&rom, PlatformName::Chip8), Err(RomError::TooLarge { size: 3585, max_size: 3584 })));
+        // ETI 660 programs load at 0x600, leaving less room
+        assert!(matches!(init(&[0; 3000], PlatformName::Eti660), Err(RomError::TooLarge { size: 3000, max_size: 2560 })));
+    }
+
+    #[test]
+    fn refuses_a_rom_larger_than_its_options_allow() {
+        let read = read("max-size", &[("game.ch8", &[0; 8]), ("game.json", br#"{"maxSize": 4}"#)]);
+        assert!(matches!(read, Err(RomError::TooLarge { size: 8, max_size: 4 })), "{:?}", read);
+    }
+
+    #[test]
+    fn refuses_octo_source() {
+        let read = read("octo-source", &[("game.8o", b": main loop again")]);
+        assert!(matches!(read, Err(RomError::UnsupportedFormat(_))), "{:?}", read);
+    }
+
+    #[test]
+    fn reports_missing_and_unreadable_files() {
+        let directory = std::env::temp_dir().join(format!("chip8-unreadable-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let missing = read_rom(&directory.join("missing.ch8"));
+        let unreadable = read_rom(&directory);
+        fs::remove_dir_all(&directory).unwrap();
+        assert!(matches!(missing, Err(RomError::NotFound)), "{:?}", missing);
+        assert!(matches!(unreadable, Err(RomError::Unreadable(_))), "{:?}", unreadable);
+    }
 }
@@ -0,0 +1,120 @@
+use iced::keyboard;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Maps keyboard keys to the sixteen CHIP-8 keypad keys, and to CHIP-8X's second keypad as keys
+/// 0x10 to 0x1F.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    keys: HashMap<String, u8>,
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+    Io(io::Error),
+    InvalidLine { line_number: usize, line: String },
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeymapError::Io(error) => write!(f, "could not read keymap: {}", error),
+            KeymapError::InvalidLine { line_number, line } => write!(f, "invalid keymap line {}: {:?} (expected `<key> = <hex digit>` or `<key> = 2:<hex digit>`)", line_number, line),
+        }
+    }
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let layout = ["0", "1", "2", "3", "q", "w", "e", "r", "a", "s", "d", "f", "z", "x", "c", "v"];
+        Self {
+            keys: layout.iter().enumerate().map(|(keypad_key, key)| (key.to_string(), keypad_key as u8)).collect(),
+        }
+    }
+}
+
+impl Keymap {
+    /// Reads a keymap file. Each non-empty line is `<key> = <keypad hex digit>`, where the key is
+    /// a single character or an iced named key such as `ArrowUp`; `#` starts a comment. A digit
+    /// written `2:<hex digit>` is on the second keypad.
+    pub fn from_file(path: &Path) -> Result<Keymap, KeymapError> {
+        let contents = fs::read_to_string(path).map_err(KeymapError::Io)?;
+        let mut keys = HashMap::new();
+        for (index, line) in contents.lines().enumerate() {
+            let entry = line.split('#').next().unwrap_or_default().trim();
+            if entry.is_empty() {
+                continue;
+            }
+            let invalid_line = || KeymapError::InvalidLine {
+                line_number: index + 1,
+                line: line.to_string(),
+            };
+            let (key, keypad_key) = entry.split_once('=').ok_or_else(invalid_line)?;
+            let (keypad_key, first_key) = match keypad_key.trim().strip_prefix("2:") {
+                Some(keypad_key) => (keypad_key, 16),
+                None => (keypad_key.trim(), 0),
+            };
+            let keypad_key = u8::from_str_radix(keypad_key.trim(), 16).ok().filter(|keypad_key| *keypad_key < 16).ok_or_else(invalid_line)? + first_key;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(invalid_line());
+            }
+            keys.insert(normalise_key_name(key), keypad_key);
+        }
+        Ok(Keymap { keys })
+    }
+
+    pub fn keypad_key(&self, key: &keyboard::Key) -> Option<u8> {
+        let name = match key.as_ref() {
+            keyboard::key::Key::Character(character) => character.to_string(),
+            keyboard::key::Key::Named(named) => format!("{:?}", named),
+            keyboard::key::Key::Unidentified => return None,
+        };
+        self.keys.get(&normalise_key_name(&name)).copied()
+    }
+}
+
+/// Single characters match regardless of case; named keys are matched exactly.
+fn normalise_key_name(name: &str) -> String {
+    if name.chars().count() == 1 {
+        name.to_lowercase()
+    } else {
+        name.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keymap(contents: &str) -> Result<Keymap, KeymapError> {
+        let path = std::env::temp_dir().join(format!("chip8-keymap-{}-{}.txt", std::process::id(), contents.len()));
+        fs::write(&path, contents).unwrap();
+        let keymap = Keymap::from_file(&path);
+        fs::remove_file(&path).unwrap();
+        keymap
+    }
+
+    fn character(key: &str) -> keyboard::Key {
+        keyboard::Key::Character(key.into())
+    }
+
+    #[test]
+    fn binds_keys_to_either_keypad() {
+        let keymap = keymap("# player one\nW = 5\nArrowUp = 2:5  # player two\nk = 2:F\n").unwrap();
+        assert_eq!(keymap.keypad_key(&character("w")), Some(0x05));
+        assert_eq!(keymap.keypad_key(&keyboard::Key::Named(keyboard::key::Named::ArrowUp)), Some(0x15));
+        assert_eq!(keymap.keypad_key(&character("K")), Some(0x1F));
+        assert_eq!(keymap.keypad_key(&character("q")), None);
+    }
+
+    #[test]
+    fn rejects_bad_second_keypad_digits() {
+        for contents in ["a = 2:10\n", "a = 3:1\n", "a = 2:\n", " = 2:1\n"] {
+            assert!(matches!(keymap(contents), Err(KeymapError::InvalidLine { line_number: 1, .. })), "{:?}", contents);
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! The emulator itself, shared by the `chip8` binary and the render benchmark.
+
+#[cfg(feature = "audio")]
+mod audio;
+mod capture;
+mod cartridge;
+mod chip8x;
+pub mod cli;
+mod coverage;
+mod cpu;
+mod disasm;
+mod font;
+pub mod headless;
+mod init;
+mod keymap;
+pub mod machine;
+mod megachip;
+mod octo;
+mod opcodes;
+pub mod palette;
+mod platform;
+mod profiler;
+mod recent;
+pub mod rotation;
+mod state;
+mod strict;
+mod timing;
+mod trace;
+pub mod ui;
+mod vip;
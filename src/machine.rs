@@ -0,0 +1,110 @@
+use crate::cpu;
+use crate::cpu::CpuFault;
+use crate::cpu::Halt;
+use crate::cpu::Hooks;
+use crate::megachip;
+use crate::platform::PlatformName;
+use crate::state::Keypad;
+use crate::state::State;
+use std::fmt;
+use std::mem;
+
+/// A full-colour picture of the display, for modes the monochrome rows can't show.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ColourFrame {
+    pub width: usize,
+    pub height: usize,
+    /// Row by row, top left first.
+    pub pixels: Vec<[u8; 3]>,
+}
+
+/// What the window and the headless runner drive, so the high-level interpreter in `cpu` and the
+/// COSMAC VIP system in `vip` can run the same ROMs.
+pub trait Machine: fmt::Debug {
+    /// Runs one 60 Hz frame. Only the high-level interpreter uses `cycles_per_tick` and `hooks`;
+    /// the VIP runs at the speed of its own hardware.
+    fn run_frame(&mut self, cycles_per_tick: u16, hooks: &mut Hooks) -> Result<(), CpuFault>;
+
+    fn keypad(&mut self) -> &mut Keypad;
+
+    /// The second keypad, on machines that have one.
+    fn second_keypad(&mut self) -> Option<&mut Keypad> {
+        None
+    }
+
+    /// The display as of the end of the last frame, one row per line.
+    fn video(&self) -> &[u128];
+
+    /// Pixels across each `video` row.
+    fn display_width(&self) -> usize {
+        64
+    }
+
+    /// The display in colour, when the machine is showing something `video` can't.
+    fn colour_video(&self) -> Option<ColourFrame> {
+        None
+    }
+
+    /// The sound played during the last frame, at `megachip::SAMPLE_RATE`. Empty when silent.
+    fn audio(&self) -> &[f32] {
+        &[]
+    }
+
+    fn memory(&self) -> &[u8];
+
+    /// Set when the program is stuck until something outside changes.
+    fn halted(&self) -> Option<Halt>;
+
+    /// Faults the program ran past since the last call.
+    fn take_warnings(&mut self) -> Vec<CpuFault>;
+}
+
+impl Machine for State {
+    fn run_frame(&mut self, cycles_per_tick: u16, hooks: &mut Hooks) -> Result<(), CpuFault> {
+        cpu::run_cycle(self, cycles_per_tick, hooks)
+    }
+
+    fn keypad(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    fn video(&self) -> &[u128] {
+        &self.video
+    }
+
+    fn display_width(&self) -> usize {
+        self.platform.display_width
+    }
+
+    fn second_keypad(&mut self) -> Option<&mut Keypad> {
+        (self.platform.name == PlatformName::Chip8X).then_some(&mut self.keypad2)
+    }
+
+    fn colour_video(&self) -> Option<ColourFrame> {
+        if let Some(colour_board) = &self.colour_board {
+            return Some(colour_board.frame(&self.video));
+        }
+        let mega = self.mega.as_ref().filter(|mega| mega.enabled)?;
+        Some(ColourFrame {
+            width: megachip::WIDTH,
+            height: megachip::HEIGHT,
+            pixels: mega.shown.clone(),
+        })
+    }
+
+    fn audio(&self) -> &[f32] {
+        self.mega.as_ref().map_or(&[], |mega| &mega.samples)
+    }
+
+    fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    fn halted(&self) -> Option<Halt> {
+        self.halted
+    }
+
+    fn take_warnings(&mut self) -> Vec<CpuFault> {
+        mem::take(&mut self.warnings)
+    }
+}
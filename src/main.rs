@@ -1,9 +1,17 @@
-mod cpu;
-mod init;
-mod opcodes;
-mod state;
-mod ui;
+use chip8::cli;
+use chip8::cli::Command;
+use chip8::headless;
+use chip8::ui;
+use clap::Parser;
+use std::process;
 
 fn main() {
-    ui::create_ui();
+    let args = cli::Args::parse();
+    match &args.command {
+        Some(Command::Run(run)) => process::exit(headless::run_rom(run)),
+        Some(Command::TraceDiff(trace_diff)) => process::exit(headless::trace_diff(trace_diff)),
+        Some(Command::Profile(profile)) => process::exit(headless::profile(profile)),
+        Some(Command::Coverage(coverage)) => process::exit(headless::coverage(coverage)),
+        None => ui::create_ui(args),
+    }
 }
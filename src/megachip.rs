@@ -0,0 +1,251 @@
+//! MegaChip8 extensions, switched on by `0011` and off by `0010`.
+//!
+//! In MegaChip mode the display is 256x192 with 8-bit colour. `DXYN` draws a sprite of
+//! `sprite_width` x `sprite_height` bytes from I, each byte a palette index and 0 transparent,
+//! blended onto the frame by the blend mode. VF reports whether any pixel landed on a pixel of
+//! the collision colour. Sprites with I in the font are drawn as ordinary 8-pixel-wide rows in white.
+//! Drawing goes to a back buffer, which `00E0` puts on screen before clearing it.
+//!
+//! `02NN` loads NN colours from I into palette entries 1 to NN, 4 bytes each: alpha, red, green and
+//! blue. `060N` plays the sound at I: a 16-bit sample rate, a 24-bit length and a reserved byte,
+//! followed by 8-bit unsigned samples. N is 0 to loop it and 1 to play it once.
+//!
+//! MegaChip builds on SUPER-CHIP, so its scrolling, `00FD` exit and `FX75`/`FX85` flag registers
+//! work too. SUPER-CHIP's 128x64 hi-res mode does not: `00FF` outside MegaChip mode faults.
+
+/// Display size in MegaChip mode.
+pub static WIDTH: usize = 256;
+pub static HEIGHT: usize = 192;
+
+/// Bytes before the samples of a digitised sound.
+static SOUND_HEADER_SIZE: usize = 6;
+/// Rate of the audio digitised sounds are resampled to as they play.
+pub static SAMPLE_RATE: u32 = 44100;
+static SAMPLES_PER_FRAME: usize = 735;
+
+/// How a sprite's colour combines with what is already on the frame, set by `080N`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    /// The sprite shows at a quarter of its strength.
+    Alpha25,
+    Alpha50,
+    Alpha75,
+    Add,
+    Multiply,
+}
+
+impl BlendMode {
+    pub fn from_number(number: u8) -> Option<BlendMode> {
+        match number {
+            0 => Some(BlendMode::Normal),
+            1 => Some(BlendMode::Alpha25),
+            2 => Some(BlendMode::Alpha50),
+            3 => Some(BlendMode::Alpha75),
+            4 => Some(BlendMode::Add),
+            5 => Some(BlendMode::Multiply),
+            _ => None,
+        }
+    }
+
+    fn blend(&self, sprite: [u8; 3], background: [u8; 3]) -> [u8; 3] {
+        let mix = |weight: u16| [0, 1, 2].map(|i| ((sprite[i] as u16 * weight + background[i] as u16 * (4 - weight)) / 4) as u8);
+        match self {
+            BlendMode::Normal => sprite,
+            BlendMode::Alpha25 => mix(1),
+            BlendMode::Alpha50 => mix(2),
+            BlendMode::Alpha75 => mix(3),
+            BlendMode::Add => [0, 1, 2].map(|i| sprite[i].saturating_add(background[i])),
+            BlendMode::Multiply => [0, 1, 2].map(|i| (sprite[i] as u16 * background[i] as u16 / 255) as u8),
+        }
+    }
+}
+
+/// A digitised sound started by `060N`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sound {
+    /// Address of the first sample.
+    pub start: usize,
+    pub length: usize,
+    pub sample_rate: u16,
+    pub looping: bool,
+    /// Samples played so far.
+    pub position: f64,
+}
+
+impl Sound {
+    /// Reads the header at `address`, or returns `None` for a sound without samples.
+    pub fn from_header(memory: &[u8], address: usize, looping: bool) -> Option<Sound> {
+        let byte = |offset: usize| memory[(address + offset) % memory.len()] as usize;
+        let sound = Sound {
+            start: (address + SOUND_HEADER_SIZE) % memory.len(),
+            length: (byte(2) << 16) | (byte(3) << 8) | byte(4),
+            sample_rate: ((byte(0) << 8) | byte(1)) as u16,
+            looping,
+            position: 0.,
+        };
+        (sound.length > 0).then_some(sound)
+    }
+}
+
+/// MegaChip state kept alongside the CHIP-8 machine on the MegaChip platform.
+#[derive(Debug, Clone)]
+pub struct MegaChip {
+    pub enabled: bool,
+    pub palette: [[u8; 3]; 256],
+    pub sprite_width: usize,
+    pub sprite_height: usize,
+    /// Opacity of the whole display, set by `05NN`.
+    pub screen_alpha: u8,
+    pub blend: BlendMode,
+    pub collision_colour: u8,
+    /// The frame being drawn, as colours and as the palette index last drawn at each pixel.
+    pub drawing: Vec<[u8; 3]>,
+    pub indices: Vec<u8>,
+    /// The frame on screen, as of the last `00E0`.
+    pub shown: Vec<[u8; 3]>,
+    pub sound: Option<Sound>,
+    /// What the sound played during the last frame, at `SAMPLE_RATE`. Empty when silent.
+    pub samples: Vec<f32>,
+}
+
+impl Default for MegaChip {
+    fn default() -> Self {
+        let mut palette = [[0; 3]; 256];
+        palette[255] = [0xFF; 3];
+        Self {
+            enabled: false,
+            palette,
+            sprite_width: 0,
+            sprite_height: 0,
+            screen_alpha: 0xFF,
+            blend: BlendMode::default(),
+            collision_colour: 0,
+            drawing: vec![[0; 3]; WIDTH * HEIGHT],
+            indices: vec![0; WIDTH * HEIGHT],
+            shown: vec![[0; 3]; WIDTH * HEIGHT],
+            sound: None,
+            samples: Vec::new(),
+        }
+    }
+}
+
+impl MegaChip {
+    /// Puts the frame drawn so far on screen, faded by the screen alpha, and starts a new one.
+    pub fn show_frame(&mut self) {
+        let alpha = self.screen_alpha as u16;
+        for (shown, drawn) in self.shown.iter_mut().zip(&self.drawing) {
+            *shown = drawn.map(|channel| (channel as u16 * alpha / 255) as u8);
+        }
+        self.drawing.fill([0; 3]);
+        self.indices.fill(0);
+    }
+
+    /// Draws one pixel of palette colour `index`, returning whether it landed on the collision colour.
+    pub fn plot(&mut self, x: usize, y: usize, index: u8) -> bool {
+        let pixel = y * WIDTH + x;
+        let collided = self.indices[pixel] != 0 && self.indices[pixel] == self.collision_colour;
+        self.indices[pixel] = index;
+        self.drawing[pixel] = self.blend.blend(self.palette[index as usize], self.drawing[pixel]);
+        collided
+    }
+
+    /// Moves the frame being drawn `right` pixels right and `down` pixels down, leaving the
+    /// uncovered edge empty.
+    pub fn scroll(&mut self, right: isize, down: isize) {
+        let drawing = self.drawing.clone();
+        let indices = self.indices.clone();
+        for y in 0..HEIGHT {
+            for x in 0..WIDTH {
+                let pixel = y * WIDTH + x;
+                let (from_x, from_y) = (x as isize - right, y as isize - down);
+                if (0..WIDTH as isize).contains(&from_x) && (0..HEIGHT as isize).contains(&from_y) {
+                    let from = from_y as usize * WIDTH + from_x as usize;
+                    self.drawing[pixel] = drawing[from];
+                    self.indices[pixel] = indices[from];
+                } else {
+                    self.drawing[pixel] = [0; 3];
+                    self.indices[pixel] = 0;
+                }
+            }
+        }
+    }
+
+    /// Plays one 60 Hz frame of the sound into `samples`, stopping it at the end unless it loops.
+    pub fn end_frame(&mut self, memory: &[u8]) {
+        self.samples.clear();
+        let Some(sound) = &mut self.sound else {
+            return;
+        };
+        let step = sound.sample_rate as f64 / SAMPLE_RATE as f64;
+        let mut finished = false;
+        for _ in 0..SAMPLES_PER_FRAME {
+            if sound.position >= sound.length as f64 {
+                if !sound.looping {
+                    finished = true;
+                    break;
+                }
+                sound.position %= sound.length as f64;
+            }
+            let sample = memory[(sound.start + sound.position as usize) % memory.len()];
+            self.samples.push((sample as f32 - 128.) / 128.);
+            sound.position += step;
+        }
+        if finished || (!sound.looping && sound.position >= sound.length as f64) {
+            self.sound = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A sound header at 0 for `samples` at 22050 Hz, followed by the samples.
+    fn memory(samples: &[u8]) -> Vec<u8> {
+        let mut memory = vec![0x56, 0x22, 0, 0, samples.len() as u8, 0];
+        memory.extend_from_slice(samples);
+        memory
+    }
+
+    #[test]
+    fn plays_a_sound_once_at_the_output_rate() {
+        let memory = memory(&[0x80, 0xFF, 0x00, 0x40]);
+        let mut mega = MegaChip {
+            sound: Sound::from_header(&memory, 0, false),
+            ..MegaChip::default()
+        };
+        mega.end_frame(&memory);
+        assert_eq!(mega.samples, [0., 0., 127. / 128., 127. / 128., -1., -1., -0.5, -0.5]);
+        assert_eq!(mega.sound, None);
+        mega.end_frame(&memory);
+        assert!(mega.samples.is_empty());
+    }
+
+    #[test]
+    fn loops_a_sound_for_the_whole_frame() {
+        let memory = memory(&[0x00, 0xFF]);
+        let mut mega = MegaChip {
+            sound: Sound::from_header(&memory, 0, true),
+            ..MegaChip::default()
+        };
+        mega.end_frame(&memory);
+        assert_eq!(mega.samples.len(), SAMPLES_PER_FRAME);
+        assert_eq!(mega.samples[4..8], [-1., -1., 127. / 128., 127. / 128.]);
+        assert!(mega.sound.is_some());
+    }
+
+    #[test]
+    fn scrolls_the_frame_being_drawn() {
+        let mut mega = MegaChip::default();
+        mega.plot(0, 0, 255);
+        mega.plot(WIDTH - 1, 0, 255);
+        mega.scroll(4, 2);
+        assert_eq!(mega.indices[2 * WIDTH + 4], 255);
+        assert_eq!(mega.drawing[2 * WIDTH + 4], [0xFF; 3]);
+        assert_eq!(mega.indices.iter().filter(|index| **index != 0).count(), 1);
+        mega.scroll(-4, 0);
+        assert_eq!(mega.indices[2 * WIDTH], 255);
+    }
+}
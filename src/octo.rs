@@ -0,0 +1,184 @@
+use crate::palette::Palette;
+use crate::platform::MAX_CYCLES_PER_TICK;
+use crate::rotation::Rotation;
+use crate::state::Quirks;
+use serde::Deserialize;
+use serde::Serialize;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// The options object Octo stores with a program. Every field is optional so partial objects from
+/// other tools still load; unknown fields are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OctoOptions {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tickrate: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fill_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background_color: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shift_quirks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_store_quirks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vf_order_quirks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub clip_quirks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jump_quirks: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub screen_rotation: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<usize>,
+}
+
+impl OctoOptions {
+    /// Instructions per 60 Hz frame, clamped to what the cycles per tick setting can hold.
+    pub fn cycles_per_tick(&self) -> Option<u16> {
+        let tickrate = self.tickrate?;
+        if tickrate > MAX_CYCLES_PER_TICK as u32 {
+            eprintln!("Warning: tickrate {} is more than the fastest speed, running at {} instead", tickrate, MAX_CYCLES_PER_TICK);
+        }
+        Some(tickrate.clamp(1, MAX_CYCLES_PER_TICK as u32) as u16)
+    }
+
+    /// A palette built from `backgroundColor` and `fillColor`, if both are valid `#RRGGBB` colours.
+    pub fn palette(&self) -> Option<Palette> {
+        let background = parse_colour(self.background_color.as_deref()?)?;
+        let foreground = parse_colour(self.fill_color.as_deref()?)?;
+        Some(Palette::Custom { background, foreground })
+    }
+
+    /// Overrides each quirk the options mention, leaving the others as they are.
+    pub fn apply_quirks(&self, quirks: &mut Quirks) {
+        let overrides = [
+            (self.shift_quirks, &mut quirks.shift),
+            (self.load_store_quirks, &mut quirks.load_store),
+            (self.vf_order_quirks, &mut quirks.vf_order),
+            (self.clip_quirks, &mut quirks.clip),
+            (self.jump_quirks, &mut quirks.jump),
+        ];
+        for (option, quirk) in overrides {
+            if let Some(enabled) = option {
+                *quirk = enabled;
+            }
+        }
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.shift_quirks = Some(quirks.shift);
+        self.load_store_quirks = Some(quirks.load_store);
+        self.vf_order_quirks = Some(quirks.vf_order);
+        self.clip_quirks = Some(quirks.clip);
+        self.jump_quirks = Some(quirks.jump);
+    }
+
+    pub fn rotation(&self) -> Option<Rotation> {
+        self.screen_rotation.and_then(Rotation::from_degrees)
+    }
+
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.background_color = Some(format_colour(palette.background()));
+        self.fill_color = Some(format_colour(palette.foreground()));
+    }
+}
+
+/// Where an options file for `rom_path` may sit, in the order they are tried: `game.json`, then `game.ch8.json`.
+pub fn sidecar_paths(rom_path: &Path) -> [PathBuf; 2] {
+    let mut with_extra_extension = rom_path.as_os_str().to_owned();
+    with_extra_extension.push(".json");
+    [rom_path.with_extension("json"), PathBuf::from(with_extra_extension)]
+}
+
+fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    let hex = colour.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let value = u32::from_str_radix(hex, 16).ok()?;
+    Some([(value >> 16) as u8, (value >> 8) as u8, value as u8])
+}
+
+fn format_colour([red, green, blue]: [u8; 3]) -> String {
+    format!("#{:02X}{:02X}{:02X}", red, green, blue)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: &str) -> OctoOptions {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn reads_quirk_keys() {
+        let options = parse(r#"{"shiftQuirks": true, "loadStoreQuirks": true, "vfOrderQuirks": false, "clipQuirks": true, "jumpQuirks": true, "vBlankQuirks": true}"#);
+        let mut quirks = Quirks::default();
+        options.apply_quirks(&mut quirks);
+        assert!(quirks.shift);
+        assert!(quirks.load_store);
+        assert!(!quirks.vf_order);
+        assert!(quirks.clip);
+        assert!(quirks.jump);
+    }
+
+    #[test]
+    fn leaves_unmentioned_quirks_alone() {
+        let mut quirks = Quirks { jump: true, ..Quirks::default() };
+        parse(r#"{"shiftQuirks": true}"#).apply_quirks(&mut quirks);
+        assert!(quirks.shift);
+        assert!(quirks.jump);
+    }
+
+    #[test]
+    fn reads_colours() {
+        let options = parse(r##"{"fillColor": "#FFCC00", "backgroundColor": "#996600", "buzzColor": "#FFAA00"}"##);
+        assert_eq!(
+            options.palette(),
+            Some(Palette::Custom {
+                background: [0x99, 0x66, 0x00],
+                foreground: [0xFF, 0xCC, 0x00],
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_bad_colours() {
+        assert_eq!(parse(r##"{"fillColor": "#FFCC0", "backgroundColor": "#996600"}"##).palette(), None);
+        assert_eq!(parse(r##"{"fillColor": "yellow", "backgroundColor": "#996600"}"##).palette(), None);
+        assert_eq!(parse(r##"{"fillColor": "#FFCC00"}"##).palette(), None);
+    }
+
+    #[test]
+    fn reads_screen_rotation() {
+        assert_eq!(parse(r#"{"screenRotation": 90}"#).rotation(), Some(Rotation::Clockwise90));
+        assert_eq!(parse(r#"{"screenRotation": 270}"#).rotation(), Some(Rotation::Clockwise270));
+        assert_eq!(parse(r#"{"screenRotation": 45}"#).rotation(), None);
+        assert_eq!(parse("{}").rotation(), None);
+    }
+
+    #[test]
+    fn clamps_tickrate() {
+        assert_eq!(parse(r#"{"tickrate": 1000}"#).cycles_per_tick(), Some(1000));
+        assert_eq!(parse(r#"{"tickrate": 0}"#).cycles_per_tick(), Some(1));
+        assert_eq!(parse(r#"{"tickrate": 100000}"#).cycles_per_tick(), Some(MAX_CYCLES_PER_TICK));
+    }
+
+    #[test]
+    fn rejects_values_of_the_wrong_type() {
+        for json in [r#"{"tickrate": "fast"}"#, r#"{"tickrate": -1}"#, r#"{"shiftQuirks": 1}"#, r#"{"screenRotation": "90"}"#] {
+            assert!(serde_json::from_str::<OctoOptions>(json).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn writes_back_what_it_reads() {
+        let mut options = parse(r#"{"tickrate": 500, "maxSize": 65024}"#);
+        options.set_quirks(Quirks::default());
+        let json = serde_json::to_string(&options).unwrap();
+        assert_eq!(parse(&json), options);
+    }
+}
@@ -1,37 +1,202 @@
 #![allow(non_snake_case)]
 
+use crate::cpu::CpuFault;
+use crate::cpu::Halt;
 use crate::init::FONT_SET_START_ADDRESS;
+use crate::megachip;
+use crate::megachip::BlendMode;
+use crate::megachip::Sound;
+use crate::state::pixel_bitmask;
+use crate::state::KeyWait;
+use crate::state::StackPolicy;
 use crate::state::State;
 
-static SCREEN_WIDTH: u8 = 64;
-static SCREEN_HEIGHT: u8 = 32;
+/// SUPER-CHIP's display width, on which DXY0 draws a 16x16 sprite.
+static SCHIP_WIDTH: usize = 128;
 
 pub fn op_00E0(state: &mut State, _opcode: u16) {
     state.video.iter_mut().for_each(|row| *row = 0);
+    if let Some(mega) = state.mega.as_mut().filter(|mega| mega.enabled) {
+        mega.show_frame();
+    }
+}
+
+pub fn op_0010(state: &mut State, _opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.enabled = false;
+    }
+}
+
+pub fn op_0011(state: &mut State, _opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.enabled = true;
+    }
+}
+
+pub fn op_01NN(state: &mut State, opcode: u16) {
+    let low = ((state.memory[state.pc as usize] as u32) << 8) | state.memory[state.offset(state.pc, 1) as usize] as u32;
+    state.pc = state.offset(state.pc, 2);
+    state.index = ((opcode as u32 & 0x00FF) << 16) | low;
+}
+
+pub fn op_02NN(state: &mut State, opcode: u16) {
+    let colours = (opcode & 0x00FF) as usize;
+    let loaded: Vec<[u8; 3]> = (0..colours).map(|colour| [1, 2, 3].map(|channel| state.memory[state.index_address(4 * colour + channel)])).collect();
+    if let Some(mega) = &mut state.mega {
+        mega.palette[1..=colours].copy_from_slice(&loaded);
+    }
+}
+
+pub fn op_03NN(state: &mut State, opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.sprite_width = match opcode & 0x00FF {
+            0 => 256,
+            width => width as usize,
+        };
+    }
+}
+
+pub fn op_04NN(state: &mut State, opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.sprite_height = match opcode & 0x00FF {
+            0 => 256,
+            height => height as usize,
+        };
+    }
+}
+
+pub fn op_05NN(state: &mut State, opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.screen_alpha = (opcode & 0x00FF) as u8;
+    }
+}
+
+pub fn op_060N(state: &mut State, opcode: u16) {
+    let sound = Sound::from_header(&state.memory, state.index as usize, opcode & 0x000F == 0);
+    if let Some(mega) = &mut state.mega {
+        mega.sound = sound;
+    }
+}
+
+pub fn op_0700(state: &mut State, _opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.sound = None;
+    }
+}
+
+pub fn op_080N(state: &mut State, opcode: u16) {
+    let Some(blend) = BlendMode::from_number((opcode & 0x000F) as u8) else {
+        state.fault = Some(CpuFault::InvalidOpcode {
+            address: instruction_address(state),
+            opcode,
+        });
+        return;
+    };
+    if let Some(mega) = &mut state.mega {
+        mega.blend = blend;
+    }
+}
+
+pub fn op_09NN(state: &mut State, opcode: u16) {
+    if let Some(mega) = &mut state.mega {
+        mega.collision_colour = (opcode & 0x00FF) as u8;
+    }
 }
 
 pub fn op_00EE(state: &mut State, _opcode: u16) {
-    state.sp = state.sp.wrapping_sub(1) % 16;
-    state.pc = state.stack[state.sp as usize];
+    if state.sp == 0 {
+        // An unlimited stack wraps around to the deepest return address it has held, so one that
+        // has held none faults whatever the policy
+        let wrap_to = state.quirks.stack_depth.slots().unwrap_or(state.stack.len());
+        let fault = CpuFault::StackUnderflow { address: instruction_address(state) };
+        if wrap_to == 0 || !stack_fault(state, fault) {
+            state.fault = Some(fault);
+            return;
+        }
+        if state.stack.len() < wrap_to {
+            state.stack.resize(wrap_to, 0);
+        }
+        state.sp = wrap_to;
+    }
+    state.sp -= 1;
+    state.pc = state.stack[state.sp];
+}
+
+pub fn op_00ED(state: &mut State, _opcode: u16) {
+    state.pc = instruction_address(state);
+    state.halted = Some(Halt::Stopped { address: state.pc });
+}
+
+pub fn op_00F2(_state: &mut State, _opcode: u16) {}
+
+pub fn op_0151(state: &mut State, _opcode: u16) {
+    if state.delay_timer != 0 {
+        state.pc = instruction_address(state);
+    }
+}
+
+pub fn op_0188(state: &mut State, _opcode: u16) {
+    state.pc = state.offset(state.pc, 2);
+}
+
+pub fn op_02A0(state: &mut State, _opcode: u16) {
+    if let Some(colour_board) = &mut state.colour_board {
+        colour_board.next_background();
+    }
 }
 
 pub fn op_1XXX(state: &mut State, opcode: u16) {
     let address = opcode & 0x0FFF;
+    if address == instruction_address(state) {
+        state.halted = Some(Halt::SelfJump { address });
+    }
     state.pc = address;
 }
 
 pub fn op_2XXX(state: &mut State, opcode: u16) {
     let address = opcode & 0x0FFF;
-    state.stack[state.sp as usize] = state.pc;
-    state.sp = state.sp.wrapping_add(1) % 16;
+    if let Some(depth) = state.quirks.stack_depth.slots().filter(|depth| state.sp >= *depth) {
+        let fault = CpuFault::StackOverflow {
+            address: instruction_address(state),
+            depth,
+        };
+        if !stack_fault(state, fault) {
+            state.fault = Some(fault);
+            return;
+        }
+        state.sp = 0;
+    }
+    if state.sp == state.stack.len() {
+        state.stack.push(state.pc);
+    } else {
+        state.stack[state.sp] = state.pc;
+    }
+    state.sp += 1;
     state.pc = address;
 }
 
+/// Applies the stack policy to an overflow or underflow, returning whether to wrap around.
+fn stack_fault(state: &mut State, fault: CpuFault) -> bool {
+    match state.stack_policy {
+        StackPolicy::Halt => false,
+        StackPolicy::Warn => {
+            state.warnings.push(fault);
+            true
+        }
+        StackPolicy::Wrap => true,
+    }
+}
+
+/// Address of the instruction being executed, as `pc` has already moved past it.
+pub fn instruction_address(state: &State) -> u16 {
+    state.offset(state.pc, state.memory.len() - 2)
+}
+
 pub fn op_3XYY(state: &mut State, opcode: u16) {
     let register = ((opcode & 0x0F00) >> 8) as usize;
     let byte = (opcode & 0x00FF) as u8;
     if state.registers[register] == byte {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+        state.pc = state.offset(state.pc, 2);
     }
 }
 
@@ -39,7 +204,7 @@ pub fn op_4XYY(state: &mut State, opcode: u16) {
     let register = ((opcode & 0x0F00) >> 8) as usize;
     let byte = (opcode & 0x00FF) as u8;
     if state.registers[register] != byte {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+        state.pc = state.offset(state.pc, 2);
     }
 }
 
@@ -47,8 +212,47 @@ pub fn op_5XY0(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
     if state.registers[register1] == state.registers[register2] {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+        state.pc = state.offset(state.pc, 2);
+    }
+}
+
+/// CHIP-8E: skips if VX is greater than VY.
+pub fn op_5XY1_8E(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    if state.registers[register1] > state.registers[register2] {
+        state.pc = state.offset(state.pc, 2);
+    }
+}
+
+/// CHIP-8X: adds VY to VX a nibble at a time, each nibble wrapping at 8, as colour zone
+/// coordinates need.
+pub fn op_5XY1_8X(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let (x, y) = (state.registers[register1], state.registers[register2]);
+    state.registers[register1] = ((((x >> 4) + (y >> 4)) % 8) << 4) | (((x & 0xF) + (y & 0xF)) % 8);
+}
+
+/// CHIP-8E: stores VX to VY at I and moves I past them.
+pub fn op_5XY2(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    for (offset, register) in (register1..=register2).enumerate() {
+        let address = state.index_address(offset);
+        state.memory[address] = state.registers[register];
     }
+    state.index = state.index_address((register1..=register2).count()) as u32;
+}
+
+/// CHIP-8E: loads VX to VY from I and moves I past them.
+pub fn op_5XY3(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    for (offset, register) in (register1..=register2).enumerate() {
+        state.registers[register] = state.memory[state.index_address(offset)];
+    }
+    state.index = state.index_address((register1..=register2).count()) as u32;
 }
 
 pub fn op_6XYY(state: &mut State, opcode: u16) {
@@ -91,58 +295,128 @@ pub fn op_8XY4(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
     let sum = (state.registers[register1] as u16).wrapping_add(state.registers[register2] as u16);
-    state.registers[15] = if sum > 255 { 1 } else { 0 };
-    state.registers[register1] = sum as u8;
+    write_with_flag(state, register1, sum as u8, if sum > 255 { 1 } else { 0 });
 }
 
 pub fn op_8XY5(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
-    state.registers[15] = if state.registers[register1] > state.registers[register2] { 1 } else { 0 };
-    state.registers[register1] = state.registers[register1].wrapping_sub(state.registers[register2]);
+    let flag = if state.registers[register1] > state.registers[register2] { 1 } else { 0 };
+    write_with_flag(state, register1, state.registers[register1].wrapping_sub(state.registers[register2]), flag);
 }
 
 pub fn op_8XY6(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    state.registers[15] = state.registers[register1] & 1;
-    state.registers[register1] >>= 1;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let source = if state.quirks.shift { state.registers[register1] } else { state.registers[register2] };
+    write_with_flag(state, register1, source >> 1, source & 1);
 }
 
 pub fn op_8XY7(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
-    state.registers[15] = if state.registers[register2] > state.registers[register1] { 1 } else { 0 };
-    state.registers[register1] = state.registers[register2].wrapping_sub(state.registers[register1]);
+    let flag = if state.registers[register2] > state.registers[register1] { 1 } else { 0 };
+    write_with_flag(state, register1, state.registers[register2].wrapping_sub(state.registers[register1]), flag);
 }
 
 pub fn op_8XYE(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    state.registers[15] = (state.registers[register1] & 0x80) >> 7;
-    state.registers[register1] <<= 1;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let source = if state.quirks.shift { state.registers[register1] } else { state.registers[register2] };
+    write_with_flag(state, register1, source << 1, (source & 0x80) >> 7);
+}
+
+/// Stores an arithmetic result and its VF flag in the order the vF order quirk asks for.
+fn write_with_flag(state: &mut State, register: usize, value: u8, flag: u8) {
+    if state.quirks.vf_order {
+        state.registers[15] = flag;
+        state.registers[register] = value;
+    } else {
+        state.registers[register] = value;
+        state.registers[15] = flag;
+    }
 }
 
 pub fn op_9XY0(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
     if state.registers[register1] != state.registers[register2] {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+        state.pc = state.offset(state.pc, 2);
+    }
+}
+
+/// CHIP-8E: multiplies VX by VY, leaving the high byte in VF.
+pub fn op_9XY1(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let product = state.registers[register1] as u16 * state.registers[register2] as u16;
+    state.registers[register1] = product as u8;
+    state.registers[15] = (product >> 8) as u8;
+}
+
+/// CHIP-8E: divides VX by VY, leaving the remainder in VF. Dividing by zero changes nothing.
+pub fn op_9XY2(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let (x, y) = (state.registers[register1], state.registers[register2]);
+    if let (Some(quotient), Some(remainder)) = (x.checked_div(y), x.checked_rem(y)) {
+        state.registers[register1] = quotient;
+        state.registers[15] = remainder;
+    }
+}
+
+/// CHIP-8E: stores the five decimal digits of the 16-bit number VX:VY at I.
+pub fn op_9XY3(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let mut num = ((state.registers[register1] as u16) << 8) | state.registers[register2] as u16;
+    for offset in (0..5).rev() {
+        let address = state.index_address(offset);
+        state.memory[address] = (num % 10) as u8;
+        num /= 10;
     }
 }
 
 pub fn op_AXXX(state: &mut State, opcode: u16) {
-    let address = opcode & 0x0FFF;
-    state.index = address;
+    state.index = (opcode & 0x0FFF) as u32;
 }
 
 pub fn op_BXXX(state: &mut State, opcode: u16) {
     let address = opcode & 0x0FFF;
-    state.pc = (state.registers[0] as u16).wrapping_add(address) % 4096;
+    let register = if state.quirks.jump { ((opcode & 0x0F00) >> 8) as usize } else { 0 };
+    state.pc = state.offset(address, state.registers[register] as usize);
+}
+
+/// CHIP-8E: jumps back NN bytes from this instruction.
+pub fn op_BBNN(state: &mut State, opcode: u16) {
+    let distance = (opcode & 0x00FF) as usize;
+    state.pc = state.offset(instruction_address(state), state.memory.len() - distance);
+}
+
+/// CHIP-8E: jumps forward NN bytes from this instruction.
+pub fn op_BFNN(state: &mut State, opcode: u16) {
+    state.pc = state.offset(instruction_address(state), (opcode & 0x00FF) as usize);
+}
+
+/// CHIP-8X: colours zones, or lines of one column, with VY's colour.
+pub fn op_BXYN(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    let register2 = ((opcode & 0x00F0) >> 4) as usize;
+    let lines = (opcode & 0x000F) as usize;
+    let (x, next, colour) = (state.registers[register1], state.registers[(register1 + 1) % 16], state.registers[register2]);
+    if let Some(colour_board) = &mut state.colour_board {
+        if lines == 0 {
+            colour_board.colour_zones(x, next, colour);
+        } else {
+            colour_board.colour_lines(x, next, lines, colour);
+        }
+    }
 }
 
 pub fn op_CXYY(state: &mut State, opcode: u16) {
     let register = ((opcode & 0x0F00) >> 8) as usize;
     let byte = (opcode & 0x00FF) as u8;
-    let random_byte: u8 = rand::random();
+    let random_byte = state.random_byte();
     state.registers[register] = byte & random_byte;
 }
 
@@ -150,36 +424,121 @@ pub fn op_DXYZ(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let register2 = ((opcode & 0x00F0) >> 4) as usize;
     let height = (opcode & 0x000F) as usize;
-    let x_pos = (state.registers[register1] % SCREEN_WIDTH) as u32;
-    let y_pos = (state.registers[register2] % SCREEN_HEIGHT) as usize;
+    if state.mega_enabled() {
+        draw_mega_sprite(state, state.registers[register1] as usize, state.registers[register2] as usize, height);
+        return;
+    }
+    let schip = state.platform.display_width == SCHIP_WIDTH;
+    // SUPER-CHIP's low-res mode draws each pixel as a 2x2 block on the 128x64 display
+    let scale = if schip && !state.hires { 2 } else { 1 };
+    let (screen_width, screen_height) = (state.platform.display_width / scale, state.video.len() / scale);
+    let x_pos = state.registers[register1] as usize % screen_width;
+    let y_pos = state.registers[register2] as usize % screen_height;
+    let (sprite_width, height) = if schip && height == 0 { (16, 16) } else { (8, height) };
 
     state.registers[15] = 0;
 
     for row in 0..height {
-        let sprite_row = state.memory[((state.index as usize).wrapping_add(row)) % 4096];
-        let bits_to_flip = (sprite_row as u64).rotate_right(x_pos + 8);
-        let row_index = (row + y_pos) % SCREEN_HEIGHT as usize;
-        state.video[row_index] ^= bits_to_flip;
-        if state.video[row_index] & bits_to_flip != bits_to_flip {
-            state.registers[15] = 1;
+        if state.quirks.clip && row + y_pos >= screen_height {
+            break;
+        }
+        let sprite_row = if sprite_width == 16 {
+            u16::from_be_bytes([state.memory[state.index_address(2 * row)], state.memory[state.index_address(2 * row + 1)]])
+        } else {
+            (state.memory[state.index_address(row)] as u16) << 8
+        };
+        let mut bits_to_flip = 0;
+        for bit in (0..sprite_width).filter(|bit| sprite_row & (0x8000 >> bit) != 0) {
+            let col = x_pos + bit;
+            if state.quirks.clip && col >= screen_width {
+                break;
+            }
+            for sub_col in 0..scale {
+                bits_to_flip |= pixel_bitmask(col % screen_width * scale + sub_col);
+            }
+        }
+        for sub_row in 0..scale {
+            let row_index = (row + y_pos) % screen_height * scale + sub_row;
+            state.video[row_index] ^= bits_to_flip;
+            if state.video[row_index] & bits_to_flip != bits_to_flip {
+                state.registers[15] = 1;
+            }
         }
     }
 }
 
+/// Draws a MegaChip sprite, clipped at the screen edges. Font sprites are `height` rows of 8 bits
+/// drawn in white; anything else is a `sprite_width` x `sprite_height` block of palette indices.
+fn draw_mega_sprite(state: &mut State, x: usize, y: usize, height: usize) {
+    let is_font = state.index < state.platform.load_address as u32;
+    let Some(mega) = &state.mega else {
+        return;
+    };
+    let (width, height) = if is_font { (8, height) } else { (mega.sprite_width, mega.sprite_height) };
+    let mut pixels = Vec::new();
+    for row in 0..height.min(megachip::HEIGHT.saturating_sub(y)) {
+        for col in 0..width.min(megachip::WIDTH.saturating_sub(x)) {
+            let colour = if is_font {
+                let bits = state.memory[state.index_address(row)];
+                if bits & (0x80 >> col) != 0 {
+                    255
+                } else {
+                    0
+                }
+            } else {
+                state.memory[state.index_address(row * width + col)]
+            };
+            if colour != 0 {
+                pixels.push((x + col, y + row, colour));
+            }
+        }
+    }
+    let Some(mega) = &mut state.mega else {
+        return;
+    };
+    let mut collided = false;
+    for (x, y, colour) in pixels {
+        collided |= mega.plot(x, y, colour);
+    }
+    state.registers[15] = collided as u8;
+}
+
 pub fn op_EX9E(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    if state.keypad[state.registers[register1] as usize] {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+    if state.keypad.is_held(state.registers[register1]) {
+        state.pc = state.offset(state.pc, 2);
     }
 }
 
 pub fn op_EXA1(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    if !state.keypad[state.registers[register1] as usize] {
-        state.pc = state.pc.wrapping_add(2) % 4096;
+    if !state.keypad.is_held(state.registers[register1]) {
+        state.pc = state.offset(state.pc, 2);
     }
 }
 
+/// CHIP-8X: skips if key VX is held on the second keypad.
+pub fn op_EXF2(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    if state.keypad2.is_held(state.registers[register1]) {
+        state.pc = state.offset(state.pc, 2);
+    }
+}
+
+/// CHIP-8X: skips unless key VX is held on the second keypad.
+pub fn op_EXF5(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    if !state.keypad2.is_held(state.registers[register1]) {
+        state.pc = state.offset(state.pc, 2);
+    }
+}
+
+/// CHIP-8E: sends VX to output port 3.
+pub fn op_FX03(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.io_port = state.registers[register1];
+}
+
 pub fn op_FX07(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     state.registers[register1] = state.delay_timer;
@@ -187,10 +546,24 @@ pub fn op_FX07(state: &mut State, opcode: u16) {
 
 pub fn op_FX0A(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    let pressed_key = state.keypad.iter().position(|key| *key);
-    match pressed_key {
-        Some(key) => state.registers[register1] = key as u8,
-        None => state.pc = state.pc.wrapping_sub(2) % 4096,
+    if !state.key_waiting {
+        state.keypad.start_wait();
+        state.key_waiting = true;
+    }
+    let key = match state.quirks.key_wait {
+        KeyWait::Held => state.keypad.any_held(),
+        KeyWait::Press => state.keypad.take_press(),
+        KeyWait::Release => state.keypad.take_release(),
+    };
+    match key {
+        Some(key) => {
+            state.registers[register1] = key;
+            state.key_waiting = false;
+        }
+        None => {
+            state.pc = instruction_address(state);
+            state.halted = Some(Halt::WaitingForKey { address: state.pc });
+        }
     }
 }
 
@@ -206,36 +579,160 @@ pub fn op_FX18(state: &mut State, opcode: u16) {
 
 pub fn op_FX1E(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    state.index = state.index.wrapping_add(state.registers[register1] as u16) % 4096;
+    state.index = state.index_address(state.registers[register1] as usize) as u32;
 }
 
 pub fn op_FX29(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    state.index = (FONT_SET_START_ADDRESS as u16).wrapping_add(5 * state.registers[register1] as u16) % 4096;
+    state.index = state.offset(FONT_SET_START_ADDRESS as u16, state.font.small_height as usize * (state.registers[register1] & 0xF) as usize) as u32;
+}
+
+pub fn op_FX30(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.index = state.offset(state.font.big_start, 10 * (state.registers[register1] & 0xF) as usize) as u32;
 }
 
 pub fn op_FX33(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
     let mut num = state.registers[register1];
-    state.memory[((state.index.wrapping_add(2)) % 4096) as usize] = num % 10;
+    let (tens, ones) = (state.index_address(1), state.index_address(2));
+    state.memory[ones] = num % 10;
     num /= 10;
-    state.memory[((state.index.wrapping_add(1)) % 4096) as usize] = num % 10;
+    state.memory[tens] = num % 10;
     num /= 10;
-    state.memory[state.index as usize] = num % 10;
+    let hundreds = state.index_address(0);
+    state.memory[hundreds] = num % 10;
 }
 
 pub fn op_FX55(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    let index = state.index as usize;
     for i in 0..register1 + 1 {
-        state.memory[index.wrapping_add(i) % 4096] = state.registers[i];
+        let address = state.index_address(i);
+        state.memory[address] = state.registers[i];
+    }
+    if !state.quirks.load_store {
+        state.index = state.index_address(register1 + 1) as u32;
     }
 }
 
 pub fn op_FX65(state: &mut State, opcode: u16) {
     let register1 = ((opcode & 0x0F00) >> 8) as usize;
-    let index = state.index as usize;
     for i in 0..register1 + 1 {
-        state.registers[i] = state.memory[index.wrapping_add(i) % 4096];
+        state.registers[i] = state.memory[state.index_address(i)];
+    }
+    if !state.quirks.load_store {
+        state.index = state.index_address(register1 + 1) as u32;
+    }
+}
+
+/// CHIP-8E: skips the next VX bytes.
+pub fn op_FX1B(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.pc = state.offset(state.pc, state.registers[register1] as usize);
+}
+
+/// CHIP-8E: sets the delay timer to VX and waits for it to run out.
+pub fn op_FX4F(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    if !state.delay_wait {
+        state.delay_timer = state.registers[register1];
+        state.delay_wait = true;
+    }
+    if state.delay_timer == 0 {
+        state.delay_wait = false;
+    } else {
+        state.pc = instruction_address(state);
+    }
+}
+
+/// CHIP-8E: reads input port 3 into VX once it has a byte ready. Nothing is attached, so it
+/// reads 0 straight away.
+pub fn op_FXE3(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.registers[register1] = 0;
+}
+
+/// CHIP-8E: reads input port 3 into VX.
+pub fn op_FXE7(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.registers[register1] = 0;
+}
+
+/// CHIP-8X: sends VX to the I/O port, where the VP-595 takes it as its tone.
+pub fn op_FXF8(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.io_port = state.registers[register1];
+}
+
+/// CHIP-8X: reads the I/O port into VX. Nothing is attached, so it reads 0.
+pub fn op_FXFB(state: &mut State, opcode: u16) {
+    let register1 = ((opcode & 0x0F00) >> 8) as usize;
+    state.registers[register1] = 0;
+}
+
+/// SUPER-CHIP: scrolls the display down N rows.
+pub fn op_00CN(state: &mut State, opcode: u16) {
+    let rows = (opcode & 0x000F) as usize;
+    match state.mega.as_mut().filter(|mega| mega.enabled) {
+        Some(mega) => mega.scroll(0, rows as isize),
+        None => {
+            let rows = rows.min(state.video.len());
+            state.video.rotate_right(rows);
+            state.video[..rows].fill(0);
+        }
     }
 }
+
+/// SUPER-CHIP: scrolls the display right 4 pixels.
+pub fn op_00FB(state: &mut State, _opcode: u16) {
+    let mask = state.row_mask();
+    match state.mega.as_mut().filter(|mega| mega.enabled) {
+        Some(mega) => mega.scroll(4, 0),
+        None => state.video.iter_mut().for_each(|row| *row = (*row >> 4) & mask),
+    }
+}
+
+/// SUPER-CHIP: scrolls the display left 4 pixels.
+pub fn op_00FC(state: &mut State, _opcode: u16) {
+    match state.mega.as_mut().filter(|mega| mega.enabled) {
+        Some(mega) => mega.scroll(-4, 0),
+        None => state.video.iter_mut().for_each(|row| *row <<= 4),
+    }
+}
+
+/// SUPER-CHIP: exits the interpreter.
+pub fn op_00FD(state: &mut State, _opcode: u16) {
+    state.pc = instruction_address(state);
+    state.halted = Some(Halt::Stopped { address: state.pc });
+}
+
+/// SUPER-CHIP: switches to the 64x32 display.
+pub fn op_00FE(state: &mut State, _opcode: u16) {
+    state.hires = false;
+}
+
+/// SUPER-CHIP: switches to the 128x64 display. MegaChip mode has its own display, so this does
+/// nothing there; outside it MegaChip's display is only 64x32.
+pub fn op_00FF(state: &mut State, opcode: u16) {
+    if state.platform.display_width == SCHIP_WIDTH {
+        state.hires = true;
+    } else if !state.mega_enabled() {
+        state.fault = Some(CpuFault::Unsupported {
+            address: instruction_address(state),
+            opcode,
+            reason: "SUPER-CHIP's 128x64 hi-res display is not emulated on MegaChip",
+        });
+    }
+}
+
+/// SUPER-CHIP: saves V0 to VX, X at most 7, in the flag registers.
+pub fn op_FX75(state: &mut State, opcode: u16) {
+    let count = (((opcode & 0x0F00) >> 8) as usize).min(7) + 1;
+    state.flags[..count].copy_from_slice(&state.registers[..count]);
+}
+
+/// SUPER-CHIP: restores V0 to VX, X at most 7, from the flag registers.
+pub fn op_FX85(state: &mut State, opcode: u16) {
+    let count = (((opcode & 0x0F00) >> 8) as usize).min(7) + 1;
+    state.registers[..count].copy_from_slice(&state.flags[..count]);
+}
@@ -0,0 +1,88 @@
+use std::fmt;
+
+/// The two colours used to show the monochrome framebuffer, both on screen and in captures.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Palette {
+    #[default]
+    Classic,
+    Amber,
+    Green,
+    Blue,
+    Lcd,
+    Octo,
+    Custom {
+        background: [u8; 3],
+        foreground: [u8; 3],
+    },
+}
+
+impl Palette {
+    pub const PRESETS: [Palette; 6] = [Palette::Classic, Palette::Amber, Palette::Green, Palette::Blue, Palette::Lcd, Palette::Octo];
+
+    /// Looks up a preset by the lowercase name used on the command line.
+    pub fn from_name(name: &str) -> Option<Palette> {
+        Self::PRESETS.into_iter().find(|palette| palette.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Palette::Classic => "classic",
+            Palette::Amber => "amber",
+            Palette::Green => "green",
+            Palette::Blue => "blue",
+            Palette::Lcd => "lcd",
+            Palette::Octo => "octo",
+            Palette::Custom { .. } => "custom",
+        }
+    }
+
+    pub fn background(&self) -> [u8; 3] {
+        match self {
+            Palette::Classic => [0x00, 0x00, 0x00],
+            Palette::Amber => [0x1A, 0x0F, 0x00],
+            Palette::Green => [0x00, 0x14, 0x00],
+            Palette::Blue => [0x00, 0x08, 0x2A],
+            Palette::Lcd => [0x9B, 0xBC, 0x0F],
+            Palette::Octo => [0x99, 0x66, 0x00],
+            Palette::Custom { background, .. } => *background,
+        }
+    }
+
+    pub fn foreground(&self) -> [u8; 3] {
+        match self {
+            Palette::Classic => [0xFF, 0xFF, 0xFF],
+            Palette::Amber => [0xFF, 0xB0, 0x00],
+            Palette::Green => [0x33, 0xFF, 0x33],
+            Palette::Blue => [0x9C, 0xD8, 0xFF],
+            Palette::Lcd => [0x0F, 0x38, 0x0F],
+            Palette::Octo => [0xFF, 0xCC, 0x00],
+            Palette::Custom { foreground, .. } => *foreground,
+        }
+    }
+
+    /// Mixes the background and foreground, `intensity` 0 being fully background and 1 fully foreground.
+    pub fn blend(&self, intensity: f32) -> [u8; 3] {
+        let background = self.background();
+        let foreground = self.foreground();
+        let mut colour = [0; 3];
+        for channel in 0..3 {
+            let mixed = background[channel] as f32 + (foreground[channel] as f32 - background[channel] as f32) * intensity;
+            colour[channel] = mixed.round() as u8;
+        }
+        colour
+    }
+}
+
+impl fmt::Display for Palette {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Palette::Classic => write!(f, "Classic"),
+            Palette::Amber => write!(f, "Amber"),
+            Palette::Green => write!(f, "Green"),
+            Palette::Blue => write!(f, "Blue"),
+            Palette::Lcd => write!(f, "LCD"),
+            Palette::Octo => write!(f, "Octo"),
+            Palette::Custom { .. } => write!(f, "Custom"),
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::font::FontSet;
+use crate::init::Rom;
+use crate::megachip;
+use crate::state::KeyWait;
+use crate::state::Quirks;
+use crate::state::StackDepth;
+use crate::state::Timing;
+use clap::ValueEnum;
+use std::fmt;
+use std::path::Path;
+
+/// The most instructions per tick the speed settings go up to, as many as Octo's fastest speed.
+pub static MAX_CYCLES_PER_TICK: u16 = 10000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum PlatformName {
+    /// This emulator's own defaults.
+    #[default]
+    Chip8,
+    /// The original COSMAC VIP interpreter.
+    Vip,
+    /// The COSMAC VIP's 64x64 hi-res interpreter.
+    Hires,
+    Eti660,
+    Dream6800,
+    /// The COSMAC VIP interpreter with CHIP-8E's extra instructions.
+    #[value(name = "chip8e")]
+    Chip8E,
+    /// The COSMAC VIP colour interpreter.
+    #[value(name = "chip8x")]
+    Chip8X,
+    /// SUPER-CHIP 1.1 on the HP 48, with its 128x64 display.
+    Schip,
+    MegaChip,
+}
+
+impl PlatformName {
+    /// Whether the platform's programs run on an unexpanded COSMAC VIP with the interpreter below
+    /// 0x200, so the emulated VIP can run them.
+    pub fn runs_on_vip(&self) -> bool {
+        matches!(self, PlatformName::Chip8 | PlatformName::Vip | PlatformName::Hires | PlatformName::Chip8E)
+    }
+
+    pub const ALL: [PlatformName; 9] = [
+        PlatformName::Chip8,
+        PlatformName::Vip,
+        PlatformName::Hires,
+        PlatformName::Eti660,
+        PlatformName::Dream6800,
+        PlatformName::Chip8E,
+        PlatformName::Chip8X,
+        PlatformName::Schip,
+        PlatformName::MegaChip,
+    ];
+
+    pub fn platform(&self) -> Platform {
+        let vip_quirks = Quirks {
+            shift: false,
+            load_store: false,
+            vf_order: false,
+            clip: true,
+            jump: false,
+            stack_depth: StackDepth::Twelve,
+            key_wait: KeyWait::Release,
+            timing: Timing::Instructions,
+        };
+        let chip8 = Platform {
+            name: *self,
+            memory_size: 4096,
+            load_address: 0x200,
+            start_address: 0x200,
+            font: FontSet::Octo,
+            display_width: 64,
+            display_height: 32,
+            quirks: Quirks::default(),
+            cycles_per_tick: 4,
+        };
+        match self {
+            PlatformName::Chip8 => chip8,
+            PlatformName::Vip => Platform {
+                font: FontSet::Vip,
+                quirks: Quirks { timing: Timing::Vip, ..vip_quirks },
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Hires => Platform {
+                start_address: 0x2C0,
+                font: FontSet::Vip,
+                display_height: 64,
+                quirks: vip_quirks,
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Eti660 => Platform {
+                load_address: 0x600,
+                start_address: 0x600,
+                font: FontSet::Eti660,
+                quirks: vip_quirks,
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Dream6800 => Platform {
+                font: FontSet::Dream6800,
+                quirks: Quirks {
+                    stack_depth: StackDepth::Sixteen,
+                    key_wait: KeyWait::Press,
+                    ..vip_quirks
+                },
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Chip8E => Platform {
+                font: FontSet::Vip,
+                quirks: Quirks { timing: Timing::Vip, ..vip_quirks },
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Chip8X => Platform {
+                load_address: 0x300,
+                start_address: 0x300,
+                font: FontSet::Vip,
+                quirks: Quirks { timing: Timing::Vip, ..vip_quirks },
+                cycles_per_tick: 9,
+                ..chip8
+            },
+            PlatformName::Schip => Platform {
+                display_width: 128,
+                display_height: 64,
+                quirks: Quirks {
+                    shift: true,
+                    load_store: true,
+                    vf_order: false,
+                    clip: true,
+                    jump: true,
+                    stack_depth: StackDepth::Sixteen,
+                    key_wait: KeyWait::Press,
+                    timing: Timing::Instructions,
+                },
+                cycles_per_tick: 30,
+                ..chip8
+            },
+            PlatformName::MegaChip => Platform {
+                memory_size: 0x1000000,
+                cycles_per_tick: 3000,
+                ..chip8
+            },
+        }
+    }
+
+    /// Best guess at the platform a ROM was written for, from its extension and the hi-res
+    /// interpreter's jump over its own machine code.
+    pub fn detect(rom_path: &Path, rom: &Rom) -> PlatformName {
+        let has_extension = |wanted: &str| rom_path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case(wanted));
+        if has_extension("mc8") {
+            PlatformName::MegaChip
+        } else if has_extension("sc8") {
+            PlatformName::Schip
+        } else if rom.bytes.starts_with(&[0x12, 0x60]) {
+            PlatformName::Hires
+        } else {
+            PlatformName::Chip8
+        }
+    }
+}
+
+impl fmt::Display for PlatformName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PlatformName::Chip8 => write!(f, "CHIP-8"),
+            PlatformName::Vip => write!(f, "COSMAC VIP"),
+            PlatformName::Hires => write!(f, "CHIP-8 hi-res"),
+            PlatformName::Eti660 => write!(f, "ETI-660"),
+            PlatformName::Dream6800 => write!(f, "DREAM 6800"),
+            PlatformName::Chip8E => write!(f, "CHIP-8E"),
+            PlatformName::Chip8X => write!(f, "CHIP-8X"),
+            PlatformName::Schip => write!(f, "SUPER-CHIP"),
+            PlatformName::MegaChip => write!(f, "MegaChip8"),
+        }
+    }
+}
+
+/// Everything about a machine that a ROM depends on: how much memory it has, where programs go, what
+/// the display and font look like, how its interpreter behaved and how fast it ran.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Platform {
+    pub name: PlatformName,
+    pub memory_size: usize,
+    /// Where the ROM is loaded.
+    pub load_address: u16,
+    /// Where execution starts, past any machine code the ROM carries for the original interpreter.
+    pub start_address: u16,
+    pub font: FontSet,
+    /// Display columns: 64, or 128 on SUPER-CHIP.
+    pub display_width: usize,
+    /// Display rows.
+    pub display_height: usize,
+    pub quirks: Quirks,
+    pub cycles_per_tick: u16,
+}
+
+impl Platform {
+    /// Size of the window's display area in low-res pixels. MegaChip's 256x192 display shows at a
+    /// quarter of the pixel size and SUPER-CHIP's 128x64 at half, so they stay as wide as the others.
+    pub fn window_size(&self) -> (usize, usize) {
+        match self.name {
+            PlatformName::MegaChip => (megachip::WIDTH / 4, megachip::HEIGHT / 4),
+            _ => (64, self.display_height * 64 / self.display_width),
+        }
+    }
+}
+
+impl Default for Platform {
+    fn default() -> Self {
+        PlatformName::default().platform()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Args;
+    use clap::Parser;
+
+    #[test]
+    fn schip_platform_parses_with_its_quirks_and_display() {
+        let args = Args::parse_from(["chip8", "--platform", "schip", "--key-wait", "held", "game.ch8"]);
+        let rom = Rom {
+            bytes: vec![0x00, 0xE0],
+            options: None,
+        };
+        let platform = args.machine.platform(Path::new("game.ch8"), &rom);
+        assert_eq!(platform.name, PlatformName::Schip);
+        assert_eq!((platform.display_width, platform.display_height), (128, 64));
+        assert_eq!(platform.window_size(), (64, 32));
+        let mut quirks = platform.quirks;
+        args.machine.apply_quirks(&mut quirks);
+        assert_eq!(
+            quirks,
+            Quirks {
+                key_wait: KeyWait::Held,
+                ..PlatformName::Schip.platform().quirks
+            }
+        );
+        assert!(quirks.shift && quirks.load_store && quirks.clip && quirks.jump);
+        assert_eq!(PlatformName::detect(Path::new("game.sc8"), &rom), PlatformName::Schip);
+    }
+
+    #[test]
+    fn every_platform_fits_its_program_font_and_display() {
+        for name in PlatformName::ALL {
+            let platform = name.platform();
+            assert_eq!(platform.name, name);
+            assert!(platform.load_address <= platform.start_address, "{}", name);
+            assert!((platform.start_address as usize) < platform.memory_size, "{}", name);
+            assert!(platform.memory_size <= 1 << 24, "{}: I is 24 bits at most", name);
+            assert!(platform.display_width <= 128 && platform.display_height <= 64, "{}", name);
+            assert!(platform.cycles_per_tick <= MAX_CYCLES_PER_TICK, "{}", name);
+            let (width, height) = platform.window_size();
+            assert_eq!(width, 64, "{}", name);
+            assert!(height > 0 && height <= 64, "{}", name);
+        }
+    }
+
+    #[test]
+    fn platform_names_are_distinct() {
+        let mut names: Vec<String> = PlatformName::ALL.iter().map(|name| name.to_string()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), PlatformName::ALL.len());
+        for name in PlatformName::ALL {
+            let value = name.to_possible_value().unwrap();
+            assert_eq!(PlatformName::from_str(value.get_name(), false), Ok(name));
+        }
+    }
+
+    #[test]
+    fn detects_platforms_from_extensions_and_the_hi_res_header() {
+        let rom = |bytes: &[u8]| Rom { bytes: bytes.to_vec(), options: None };
+        assert_eq!(PlatformName::detect(Path::new("game.MC8"), &rom(&[0x00, 0xE0])), PlatformName::MegaChip);
+        assert_eq!(PlatformName::detect(Path::new("game.sc8"), &rom(&[0x12, 0x60])), PlatformName::Schip);
+        assert_eq!(PlatformName::detect(Path::new("game.ch8"), &rom(&[0x12, 0x60])), PlatformName::Hires);
+        assert_eq!(PlatformName::detect(Path::new("game.ch8"), &rom(&[0x12, 0x00])), PlatformName::Chip8);
+    }
+}
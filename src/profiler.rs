@@ -0,0 +1,214 @@
+use crate::disasm;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Every address a 16-bit program counter can hold.
+static ADDRESSES: usize = 0x10000;
+
+/// Deepest call chain followed before the oldest open call is forgotten, so ROMs that leave
+/// subroutines with a jump don't grow the call list forever.
+static MAX_OPEN_CALLS: usize = 64;
+
+/// Counts executed instructions by address, by opcode pattern and by subroutine.
+#[derive(Debug, Clone)]
+pub struct Profiler {
+    instructions: u64,
+    addresses: Vec<AddressStats>,
+    patterns: BTreeMap<&'static str, u64>,
+    subroutines: BTreeMap<u16, SubroutineStats>,
+    /// Target of each `2NNN` not yet returned from, with the instruction count when it was called.
+    open_calls: Vec<(u16, u64)>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AddressStats {
+    pub executions: u64,
+    /// The opcode last executed here, which differs from the ROM in self-modifying code.
+    pub opcode: u16,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SubroutineStats {
+    pub calls: u64,
+    pub returns: u64,
+    /// Instructions executed between each call and its `00EE`, including nested calls.
+    pub instructions: u64,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            instructions: 0,
+            addresses: vec![AddressStats::default(); ADDRESSES],
+            patterns: BTreeMap::new(),
+            subroutines: BTreeMap::new(),
+            open_calls: Vec::new(),
+        }
+    }
+}
+
+impl Profiler {
+    pub fn record(&mut self, pc: u16, opcode: u16) {
+        self.instructions += 1;
+        let length = self.addresses.len();
+        let address = &mut self.addresses[pc as usize % length];
+        address.executions += 1;
+        address.opcode = opcode;
+        *self.patterns.entry(disasm::pattern(opcode)).or_default() += 1;
+
+        if opcode & 0xF000 == 0x2000 {
+            let target = opcode & 0x0FFF;
+            self.subroutines.entry(target).or_default().calls += 1;
+            if self.open_calls.len() == MAX_OPEN_CALLS {
+                self.open_calls.remove(0);
+            }
+            self.open_calls.push((target, self.instructions));
+        } else if opcode == 0x00EE {
+            if let Some((target, called_at)) = self.open_calls.pop() {
+                let subroutine = self.subroutines.entry(target).or_default();
+                subroutine.returns += 1;
+                subroutine.instructions += self.instructions - called_at;
+            }
+        }
+    }
+
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Every address that has been executed, in address order.
+    pub fn executed_addresses(&self) -> impl Iterator<Item = (u16, AddressStats)> + '_ {
+        self.addresses
+            .iter()
+            .enumerate()
+            .filter(|(_, stats)| stats.executions > 0)
+            .map(|(address, stats)| (address as u16, *stats))
+    }
+
+    /// Execution count of the hottest address, for scaling heat maps.
+    pub fn hottest(&self) -> u64 {
+        self.addresses.iter().map(|stats| stats.executions).max().unwrap_or_default()
+    }
+
+    /// Plain-text tables of the `limit` hottest addresses, every opcode pattern and every subroutine.
+    pub fn report(&self, limit: usize) -> String {
+        let percent = |count: u64| 100. * count as f64 / self.instructions.max(1) as f64;
+        let mut report = format!("Instructions executed: {}\n\n", self.instructions);
+
+        let mut addresses: Vec<(u16, AddressStats)> = self.executed_addresses().collect();
+        addresses.sort_by_key(|(_, stats)| Reverse(stats.executions));
+        report.push_str("Hot addresses\nADDR        COUNT       %  INSTRUCTION\n");
+        for (address, stats) in addresses.iter().take(limit) {
+            let _ = writeln!(
+                report,
+                "{:03X}  {:>12}  {:>6.2}  {}",
+                address,
+                stats.executions,
+                percent(stats.executions),
+                disasm::disassemble(stats.opcode)
+            );
+        }
+
+        let mut patterns: Vec<(&str, u64)> = self.patterns.iter().map(|(pattern, count)| (*pattern, *count)).collect();
+        patterns.sort_by_key(|(_, count)| Reverse(*count));
+        report.push_str("\nInstruction types\nTYPE        COUNT       %\n");
+        for (pattern, count) in patterns {
+            let _ = writeln!(report, "{}  {:>11}  {:>6.2}", pattern, count, percent(count));
+        }
+
+        let mut subroutines: Vec<(u16, SubroutineStats)> = self.subroutines.iter().map(|(address, stats)| (*address, *stats)).collect();
+        subroutines.sort_by_key(|(_, stats)| Reverse(stats.instructions));
+        report.push_str("\nSubroutines\nADDR     CALLS  INSTRUCTIONS       %  PER CALL\n");
+        for (address, stats) in subroutines {
+            let per_call = stats.instructions as f64 / stats.returns.max(1) as f64;
+            let _ = writeln!(
+                report,
+                "{:03X}  {:>8}  {:>12}  {:>6.2}  {:>8.1}",
+                address,
+                stats.calls,
+                stats.instructions,
+                percent(stats.instructions),
+                per_call
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(instructions: &[(u16, u16)]) -> Profiler {
+        let mut profiler = Profiler::default();
+        for (pc, opcode) in instructions {
+            profiler.record(*pc, *opcode);
+        }
+        profiler
+    }
+
+    #[test]
+    fn counts_instructions_by_address_and_pattern() {
+        let profiler = run(&[(0x200, 0x6001), (0x202, 0x7001), (0x204, 0x1202), (0x202, 0x7001), (0x204, 0x1202)]);
+        assert_eq!(profiler.instructions(), 5);
+        let executed: Vec<(u16, u64)> = profiler.executed_addresses().map(|(address, stats)| (address, stats.executions)).collect();
+        assert_eq!(executed, [(0x200, 1), (0x202, 2), (0x204, 2)]);
+        assert_eq!(profiler.hottest(), 2);
+        assert_eq!(profiler.patterns.get("7XNN"), Some(&2));
+        assert_eq!(profiler.patterns.get("1NNN"), Some(&2));
+    }
+
+    #[test]
+    fn attributes_instructions_between_a_call_and_its_return() {
+        let profiler = run(&[(0x200, 0x2300), (0x300, 0x6001), (0x302, 0x00EE), (0x202, 0x2300), (0x300, 0x6001), (0x302, 0x00EE)]);
+        let subroutine = profiler.subroutines[&0x300];
+        assert_eq!(
+            subroutine,
+            SubroutineStats {
+                calls: 2,
+                returns: 2,
+                instructions: 4
+            }
+        );
+    }
+
+    #[test]
+    fn nested_calls_count_towards_their_callers() {
+        let profiler = run(&[(0x200, 0x2300), (0x300, 0x2400), (0x400, 0x6001), (0x402, 0x00EE), (0x302, 0x00EE)]);
+        assert_eq!(
+            profiler.subroutines[&0x400],
+            SubroutineStats {
+                calls: 1,
+                returns: 1,
+                instructions: 2
+            }
+        );
+        assert_eq!(
+            profiler.subroutines[&0x300],
+            SubroutineStats {
+                calls: 1,
+                returns: 1,
+                instructions: 4
+            }
+        );
+    }
+
+    #[test]
+    fn a_return_without_a_call_is_only_counted_as_an_instruction() {
+        let profiler = run(&[(0x200, 0x00EE), (0x300, 0x2400), (0x400, 0x00EE), (0x302, 0x00EE)]);
+        assert_eq!(profiler.instructions(), 4);
+        assert_eq!(profiler.subroutines.len(), 1);
+        assert_eq!(
+            profiler.subroutines[&0x400],
+            SubroutineStats {
+                calls: 1,
+                returns: 1,
+                instructions: 1
+            }
+        );
+        assert!(profiler
+            .report(10)
+            .contains("Subroutines\nADDR     CALLS  INSTRUCTIONS       %  PER CALL\n400         1             1   25.00       1.0\n"));
+    }
+}
@@ -0,0 +1,48 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+static MAX_RECENT_ROMS: usize = 8;
+
+/// A previously loaded ROM, shown by its path in the recent ROMs list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentRom(pub PathBuf);
+
+impl fmt::Display for RecentRom {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0.display())
+    }
+}
+
+fn recent_roms_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|config_dir| config_dir.join("chip8").join("recent_roms.txt"))
+}
+
+/// Reads the recent ROMs list, most recent first. A missing or unreadable list is treated as empty.
+pub fn load_recent_roms() -> Vec<RecentRom> {
+    recent_roms_file()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .map(|contents| {
+            contents
+                .lines()
+                .filter(|line| !line.is_empty())
+                .map(|line| RecentRom(PathBuf::from(line)))
+                .take(MAX_RECENT_ROMS)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Moves `rom_path` to the front of the list and saves it. Failing to save only loses the history, so errors are ignored.
+pub fn add_recent_rom(recent_roms: &mut Vec<RecentRom>, rom_path: PathBuf) {
+    recent_roms.retain(|recent_rom| recent_rom.0 != rom_path);
+    recent_roms.insert(0, RecentRom(rom_path));
+    recent_roms.truncate(MAX_RECENT_ROMS);
+    if let Some(path) = recent_roms_file() {
+        let contents: String = recent_roms.iter().map(|recent_rom| format!("{}\n", recent_rom.0.display())).collect();
+        if let Some(parent) = path.parent() {
+            let _ = fs::create_dir_all(parent);
+        }
+        let _ = fs::write(path, contents);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt;
+
+/// Clockwise rotation applied to the displayed framebuffer, for games designed to be played with the screen on its side.
+/// Input is keyboard only, so there is no on-screen keypad to rotate with it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Rotation {
+    #[default]
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub const ALL: [Rotation; 4] = [Rotation::None, Rotation::Clockwise90, Rotation::Clockwise180, Rotation::Clockwise270];
+
+    /// Maps Octo's `screenRotation` degrees; anything other than a multiple of 90 is rejected.
+    pub fn from_degrees(degrees: u16) -> Option<Rotation> {
+        match degrees % 360 {
+            0 => Some(Rotation::None),
+            90 => Some(Rotation::Clockwise90),
+            180 => Some(Rotation::Clockwise180),
+            270 => Some(Rotation::Clockwise270),
+            _ => None,
+        }
+    }
+
+    pub fn degrees(&self) -> u16 {
+        match self {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+
+    pub fn is_sideways(&self) -> bool {
+        matches!(self, Rotation::Clockwise90 | Rotation::Clockwise270)
+    }
+
+    /// Width and height of a `width` x `height` image after rotating it.
+    pub fn rotated_size(&self, width: usize, height: usize) -> (usize, usize) {
+        if self.is_sideways() {
+            (height, width)
+        } else {
+            (width, height)
+        }
+    }
+
+    /// Rotates a row-major `width` x `height` image.
+    pub fn rotate<T: Copy>(&self, pixels: &[T], width: usize, height: usize) -> Vec<T> {
+        let (rotated_width, rotated_height) = self.rotated_size(width, height);
+        let mut rotated = Vec::with_capacity(pixels.len());
+        for y in 0..rotated_height {
+            for x in 0..rotated_width {
+                let (source_x, source_y) = match self {
+                    Rotation::None => (x, y),
+                    Rotation::Clockwise90 => (y, height - 1 - x),
+                    Rotation::Clockwise180 => (width - 1 - x, height - 1 - y),
+                    Rotation::Clockwise270 => (width - 1 - y, x),
+                };
+                rotated.push(pixels[source_y * width + source_x]);
+            }
+        }
+        rotated
+    }
+}
+
+impl fmt::Display for Rotation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}°", self.degrees())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 3 x 2 image:
+    /// ```text
+    /// 0 1 2
+    /// 3 4 5
+    /// ```
+    const PIXELS: [u8; 6] = [0, 1, 2, 3, 4, 5];
+
+    #[test]
+    fn rotates_clockwise_in_quarter_turns() {
+        assert_eq!(Rotation::None.rotate(&PIXELS, 3, 2), PIXELS);
+        assert_eq!(Rotation::Clockwise90.rotate(&PIXELS, 3, 2), [3, 0, 4, 1, 5, 2]);
+        assert_eq!(Rotation::Clockwise180.rotate(&PIXELS, 3, 2), [5, 4, 3, 2, 1, 0]);
+        assert_eq!(Rotation::Clockwise270.rotate(&PIXELS, 3, 2), [2, 5, 1, 4, 0, 3]);
+    }
+
+    #[test]
+    fn quarter_turns_swap_width_and_height() {
+        assert_eq!(Rotation::None.rotated_size(64, 32), (64, 32));
+        assert_eq!(Rotation::Clockwise90.rotated_size(64, 32), (32, 64));
+        assert_eq!(Rotation::Clockwise180.rotated_size(64, 32), (64, 32));
+        assert_eq!(Rotation::Clockwise270.rotated_size(64, 32), (32, 64));
+    }
+
+    #[test]
+    fn reads_octo_screen_rotation_degrees() {
+        for rotation in Rotation::ALL {
+            assert_eq!(Rotation::from_degrees(rotation.degrees()), Some(rotation));
+        }
+        assert_eq!(Rotation::from_degrees(450), Some(Rotation::Clockwise90));
+        assert_eq!(Rotation::from_degrees(45), None);
+    }
+}
@@ -1,13 +1,300 @@
-#[derive(Debug, Clone, Copy)]
+use crate::chip8x::ColourBoard;
+use crate::cpu::CpuFault;
+use crate::cpu::Halt;
+use crate::font::FontLayout;
+use crate::megachip::MegaChip;
+use crate::platform::Platform;
+use clap::ValueEnum;
+use std::fmt;
+
+#[derive(Debug, Clone)]
 pub struct State {
     pub registers: [u8; 16],
-    pub memory: [u8; 4096],
-    pub stack: [u16; 16],
-    pub keypad: [bool; 16],
-    pub video: [u64; 32],
-    pub index: u16,
+    /// As much memory as the platform has.
+    pub memory: Vec<u8>,
+    pub stack: Vec<u16>,
+    pub keypad: Keypad,
+    /// CHIP-8X's second keypad, read by EXF2 and EXF5.
+    pub keypad2: Keypad,
+    /// One row per display line, with column 0 in the most significant bit. Displays narrower
+    /// than 128 pixels leave the low bits clear.
+    pub video: Vec<u128>,
+    /// 24 bits wide on MegaChip, 16 everywhere else.
+    pub index: u32,
     pub pc: u16,
-    pub sp: u8,
+    pub sp: usize,
     pub delay_timer: u8,
     pub sound_timer: u8,
+    pub quirks: Quirks,
+    /// Set by an instruction that cannot run; the CPU loop takes it and stops.
+    pub fault: Option<CpuFault>,
+    /// Faults the program was allowed to run past, waiting for the caller to report them.
+    pub warnings: Vec<CpuFault>,
+    /// Set when the last instruction left the program stuck until something outside changes.
+    pub halted: Option<Halt>,
+    pub stack_policy: StackPolicy,
+    /// Random number generator state for CXNN. Setting it replays the same random bytes.
+    pub rng: u64,
+    pub font: FontLayout,
+    pub platform: Platform,
+    /// Machine cycles the last frame ran past its budget under VIP timing, taken from the next.
+    pub cycle_debt: u32,
+    /// MegaChip mode and display, on the MegaChip platform only.
+    pub mega: Option<MegaChip>,
+    /// CHIP-8X colours, on the CHIP-8X platform only.
+    pub colour_board: Option<ColourBoard>,
+    /// Last byte written to the I/O port. Nothing is attached to it, so reading it gives 0.
+    pub io_port: u8,
+    /// Set while a CHIP-8E FX4F waits for the delay timer it started.
+    pub delay_wait: bool,
+    /// Set while an FX0A waits for a key.
+    pub key_waiting: bool,
+    /// SUPER-CHIP's 128x64 mode, set by 00FF and cleared by 00FE. Low-res pixels are drawn 2x2.
+    pub hires: bool,
+    /// SUPER-CHIP's flag registers, saved by `FX75` and restored by `FX85`.
+    pub flags: [u8; 8],
+}
+
+impl State {
+    /// `address` plus `offset`, wrapped around the end of memory.
+    pub fn offset(&self, address: u16, offset: usize) -> u16 {
+        ((address as usize + offset) % self.memory.len()) as u16
+    }
+
+    /// I plus `offset`, wrapped around the end of memory.
+    pub fn index_address(&self, offset: usize) -> usize {
+        (self.index as usize + offset) % self.memory.len()
+    }
+
+    /// Bits of a `video` row that are on the display.
+    pub fn row_mask(&self) -> u128 {
+        !u128::MAX.checked_shr(self.platform.display_width as u32).unwrap_or(0)
+    }
+
+    /// Whether MegaChip mode is on.
+    pub fn mega_enabled(&self) -> bool {
+        self.mega.as_ref().is_some_and(|mega| mega.enabled)
+    }
+
+    /// Next byte from a SplitMix64 sequence, which is good enough for games and works with any seed.
+    pub fn random_byte(&mut self) -> u8 {
+        self.rng = self.rng.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.rng;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        ((z ^ (z >> 31)) >> 56) as u8
+    }
+}
+
+/// Behaviours that differ between CHIP-8 interpreters. The default is what this emulator has always done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift VX in place instead of shifting VY into VX.
+    pub shift: bool,
+    /// FX55 and FX65 leave I unchanged instead of advancing it past the last register.
+    pub load_store: bool,
+    /// 8XY4 to 8XYE write the result after VF, so the result wins when X is F.
+    pub vf_order: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around.
+    pub clip: bool,
+    /// BNNN jumps to NNN plus VX instead of NNN plus V0.
+    pub jump: bool,
+    pub stack_depth: StackDepth,
+    pub key_wait: KeyWait,
+    pub timing: Timing,
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Self {
+            shift: true,
+            load_store: true,
+            vf_order: true,
+            clip: false,
+            jump: false,
+            stack_depth: StackDepth::Sixteen,
+            key_wait: KeyWait::Held,
+            timing: Timing::Instructions,
+        }
+    }
+}
+
+/// The hex keypad: which keys are held, and which went down or up since the last frame.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Keypad {
+    held: [bool; 16],
+    pressed: [bool; 16],
+    released: [bool; 16],
+    /// Keys that went down since `start_wait`.
+    pressed_while_waiting: [bool; 16],
+}
+
+impl Keypad {
+    pub fn press(&mut self, key: u8) {
+        let key = key as usize % 16;
+        if !self.held[key] {
+            self.pressed[key] = true;
+            self.pressed_while_waiting[key] = true;
+        }
+        self.held[key] = true;
+    }
+
+    pub fn release(&mut self, key: u8) {
+        let key = key as usize % 16;
+        if self.held[key] {
+            self.released[key] = true;
+        }
+        self.held[key] = false;
+    }
+
+    pub fn is_held(&self, key: u8) -> bool {
+        self.held[key as usize % 16]
+    }
+
+    /// Lowest key that is held down.
+    pub fn any_held(&self) -> Option<u8> {
+        self.held.iter().position(|held| *held).map(|key| key as u8)
+    }
+
+    /// Lowest key that went down since the last frame, consuming the press.
+    pub fn take_press(&mut self) -> Option<u8> {
+        let key = self.pressed.iter().position(|pressed| *pressed)?;
+        self.pressed[key] = false;
+        Some(key as u8)
+    }
+
+    /// Starts waiting for a key, forgetting keys that went down before.
+    pub fn start_wait(&mut self) {
+        self.pressed_while_waiting = [false; 16];
+    }
+
+    /// Lowest key that went down since `start_wait` and came up since the last frame, consuming
+    /// the release.
+    pub fn take_release(&mut self) -> Option<u8> {
+        let key = (0..16).find(|key| self.released[*key] && self.pressed_while_waiting[*key])?;
+        self.released[key] = false;
+        self.pressed_while_waiting[key] = false;
+        Some(key as u8)
+    }
+
+    /// Forgets presses and releases the program has had a frame to see.
+    pub fn end_frame(&mut self) {
+        self.pressed = [false; 16];
+        self.released = [false; 16];
+    }
+}
+
+/// What FX0A waits for before storing a key.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum KeyWait {
+    /// Any key that is down, even one held since before the instruction.
+    #[default]
+    Held,
+    /// A key going down while waiting.
+    Press,
+    /// A key going down and back up while waiting, as on the COSMAC VIP.
+    Release,
+}
+
+impl KeyWait {
+    pub const ALL: [KeyWait; 3] = [KeyWait::Held, KeyWait::Press, KeyWait::Release];
+}
+
+impl fmt::Display for KeyWait {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyWait::Held => write!(f, "Any held key"),
+            KeyWait::Press => write!(f, "Key press"),
+            KeyWait::Release => write!(f, "Key release (VIP)"),
+        }
+    }
+}
+
+/// How much a frame runs before the timers tick.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum Timing {
+    /// A fixed number of instructions, set by the speed.
+    #[default]
+    Instructions,
+    /// As many instructions as the COSMAC VIP interpreter fits in a frame, with sprites drawn
+    /// only after the display interrupt.
+    Vip,
+}
+
+impl Timing {
+    pub const ALL: [Timing; 2] = [Timing::Instructions, Timing::Vip];
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timing::Instructions => write!(f, "Instructions per frame"),
+            Timing::Vip => write!(f, "VIP machine cycles"),
+        }
+    }
+}
+
+/// How many return addresses 2NNN can push before the stack is full.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StackDepth {
+    /// The COSMAC VIP interpreter's 12 levels.
+    #[value(name = "12")]
+    Twelve,
+    #[default]
+    #[value(name = "16")]
+    Sixteen,
+    Unlimited,
+}
+
+impl StackDepth {
+    pub const ALL: [StackDepth; 3] = [StackDepth::Twelve, StackDepth::Sixteen, StackDepth::Unlimited];
+
+    pub fn slots(&self) -> Option<usize> {
+        match self {
+            StackDepth::Twelve => Some(12),
+            StackDepth::Sixteen => Some(16),
+            StackDepth::Unlimited => None,
+        }
+    }
+}
+
+impl fmt::Display for StackDepth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackDepth::Twelve => write!(f, "12 (VIP)"),
+            StackDepth::Sixteen => write!(f, "16"),
+            StackDepth::Unlimited => write!(f, "Unlimited"),
+        }
+    }
+}
+
+/// What happens when a program calls past the top of the stack or returns from an empty one.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum StackPolicy {
+    /// Stop with a CPU fault.
+    Halt,
+    /// Report a warning and wrap around.
+    #[default]
+    Warn,
+    /// Silently wrap around like the original interpreters, overwriting the oldest return address.
+    Wrap,
+}
+
+impl StackPolicy {
+    pub const ALL: [StackPolicy; 3] = [StackPolicy::Halt, StackPolicy::Warn, StackPolicy::Wrap];
+}
+
+impl fmt::Display for StackPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StackPolicy::Halt => write!(f, "Halt"),
+            StackPolicy::Warn => write!(f, "Warn"),
+            StackPolicy::Wrap => write!(f, "Wrap around"),
+        }
+    }
+}
+
+/// Bitmask selecting column `col` of a `State::video` row; column 0 is the most significant bit.
+pub fn pixel_bitmask(col: usize) -> u128 {
+    1u128.rotate_right(col as u32 + 1)
 }
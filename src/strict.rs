@@ -0,0 +1,182 @@
+use crate::cpu::CpuFault;
+use crate::disasm;
+use crate::init::FONT_SET_START_ADDRESS;
+use crate::state::State;
+use std::collections::HashSet;
+use std::mem;
+use std::mem::Discriminant;
+use std::ops::Range;
+
+/// Checks each instruction before it runs for things well-behaved programs don't do: writing
+/// below the program, reading memory nothing wrote, running code written at runtime, and
+/// jumping to odd addresses or outside the ROM.
+#[derive(Debug, Clone)]
+pub struct StrictMode {
+    /// Stop before an instruction that breaks the rules instead of only reporting it.
+    pub pause: bool,
+    program: Range<usize>,
+    memory_size: usize,
+    /// Bytes holding something meaningful: the font, the ROM and anything written since.
+    initialised: Vec<bool>,
+    /// Bytes written by the program itself.
+    modified: Vec<bool>,
+    /// Instruction we last stopped at, let through once so the program can be resumed.
+    resume_at: Option<u16>,
+    /// Each kind of problem already reported at each instruction, so a loop warns only once.
+    reported: HashSet<(u16, Discriminant<CpuFault>)>,
+}
+
+impl StrictMode {
+    /// Starts checking a program of `rom_size` bytes freshly loaded into `state`.
+    pub fn new(state: &State, rom_size: usize, pause: bool) -> Self {
+        let program_start = state.platform.load_address as usize;
+        let program = program_start..program_start + rom_size;
+        let memory_size = state.memory.len();
+        let mut initialised = vec![false; memory_size];
+        initialised[FONT_SET_START_ADDRESS..state.font.end()].fill(true);
+        initialised[program.clone()].fill(true);
+        Self {
+            pause,
+            program,
+            memory_size,
+            initialised,
+            modified: vec![false; memory_size],
+            resume_at: None,
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Reports problems with the instruction at `pc` as warnings, or returns the first as a fault
+    /// when pausing. Each kind of problem is only warned about once per instruction. Must be called
+    /// before the instruction runs.
+    pub fn check(&mut self, state: &mut State, pc: u16, opcode: u16) -> Result<(), CpuFault> {
+        let mut problems = Vec::new();
+        if self.modified[pc as usize % self.memory_size] || self.modified[(pc as usize + 1) % self.memory_size] {
+            problems.push(CpuFault::ExecutedWrittenMemory { address: pc });
+        }
+
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let (reads, writes) = match disasm::pattern(opcode) {
+            "DXYN" => (opcode as usize & 0x000F, 0),
+            "FX65" => (x + 1, 0),
+            "FX55" => (0, x + 1),
+            "FX33" => (0, 3),
+            _ => (0, 0),
+        };
+        let index = state.index as usize;
+        if let Some(target) = (index..index + reads).map(|target| target % self.memory_size).find(|target| !self.initialised[*target]) {
+            problems.push(CpuFault::ReadUninitialisedMemory { address: pc, target: target as u16 });
+        }
+        if let Some(target) = (index..index + writes).map(|target| target % self.memory_size).find(|target| *target < self.program.start) {
+            problems.push(CpuFault::WroteBelowProgram { address: pc, target: target as u16 });
+        }
+
+        let jump_target = match disasm::pattern(opcode) {
+            "1NNN" | "2NNN" => Some(opcode & 0x0FFF),
+            "BNNN" => {
+                let register = if state.quirks.jump { x } else { 0 };
+                Some(state.offset(opcode & 0x0FFF, state.registers[register] as usize))
+            }
+            _ => None,
+        };
+        if let Some(target) = jump_target.filter(|target| target % 2 == 1 || !self.program.contains(&(*target as usize))) {
+            problems.push(CpuFault::BadJump { address: pc, target });
+        }
+
+        if self.pause && !problems.is_empty() && self.resume_at != Some(pc) {
+            self.resume_at = Some(pc);
+            let first = problems.remove(0);
+            self.reported.insert((pc, mem::discriminant(&first)));
+            self.warn(state, pc, problems);
+            return Err(first);
+        }
+        self.resume_at = None;
+        self.warn(state, pc, problems);
+        for target in (index..index + writes).map(|target| target % self.memory_size) {
+            self.initialised[target] = true;
+            self.modified[target] = true;
+        }
+        Ok(())
+    }
+
+    fn warn(&mut self, state: &mut State, pc: u16, problems: Vec<CpuFault>) {
+        state.warnings.extend(problems.into_iter().filter(|problem| self.reported.insert((pc, mem::discriminant(problem)))));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Font;
+    use crate::init;
+    use crate::platform::PlatformName;
+
+    /// A fresh four-byte program and strict mode watching it.
+    fn strict(pause: bool) -> (State, StrictMode) {
+        let platform = PlatformName::Chip8.platform();
+        let state = init::init_state(&[0x00, 0xE0, 0x12, 0x02], platform, platform.quirks, &Font::default()).unwrap();
+        let strict = StrictMode::new(&state, 4, pause);
+        (state, strict)
+    }
+
+    #[test]
+    fn flags_each_kind_of_problem() {
+        let (mut state, mut strict) = strict(false);
+        state.index = 0x100;
+        strict.check(&mut state, 0x200, 0xF055).unwrap();
+        state.index = 0x300;
+        strict.check(&mut state, 0x200, 0xD011).unwrap();
+        strict.check(&mut state, 0x200, 0x1201).unwrap();
+        strict.check(&mut state, 0x202, 0x1300).unwrap();
+        state.index = 0x202;
+        strict.check(&mut state, 0x200, 0xF055).unwrap();
+        strict.check(&mut state, 0x202, 0x0000).unwrap();
+        assert_eq!(
+            state.warnings,
+            [
+                CpuFault::WroteBelowProgram { address: 0x200, target: 0x100 },
+                CpuFault::ReadUninitialisedMemory { address: 0x200, target: 0x300 },
+                CpuFault::BadJump { address: 0x200, target: 0x201 },
+                CpuFault::BadJump { address: 0x202, target: 0x300 },
+                CpuFault::ExecutedWrittenMemory { address: 0x202 },
+            ]
+        );
+    }
+
+    #[test]
+    fn leaves_well_behaved_instructions_alone() {
+        let (mut state, mut strict) = strict(false);
+        state.index = 0x50;
+        strict.check(&mut state, 0x200, 0xD015).unwrap();
+        strict.check(&mut state, 0x202, 0x1202).unwrap();
+        state.index = 0x300;
+        strict.check(&mut state, 0x200, 0xF333).unwrap();
+        strict.check(&mut state, 0x200, 0xF265).unwrap();
+        assert!(state.warnings.is_empty());
+    }
+
+    #[test]
+    fn warns_about_each_kind_of_problem_once_per_instruction() {
+        let (mut state, mut strict) = strict(false);
+        for _ in 0..3 {
+            strict.check(&mut state, 0x200, 0x1201).unwrap();
+        }
+        strict.check(&mut state, 0x200, 0x1300).unwrap();
+        strict.check(&mut state, 0x202, 0x1201).unwrap();
+        assert_eq!(
+            state.warnings,
+            [CpuFault::BadJump { address: 0x200, target: 0x201 }, CpuFault::BadJump { address: 0x202, target: 0x201 }]
+        );
+    }
+
+    #[test]
+    fn pauses_before_the_instruction_and_lets_it_through_on_resume() {
+        let (mut state, mut strict) = strict(true);
+        let fault = CpuFault::BadJump { address: 0x200, target: 0x201 };
+        assert_eq!(strict.check(&mut state, 0x200, 0x1201), Err(fault));
+        assert_eq!(strict.check(&mut state, 0x200, 0x1201), Ok(()));
+        // The fault that paused isn't reported again as a warning, but the next visit pauses again
+        assert!(state.warnings.is_empty());
+        assert_eq!(strict.check(&mut state, 0x200, 0x1201), Err(fault));
+    }
+}
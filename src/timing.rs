@@ -0,0 +1,177 @@
+//! Machine-cycle costs of the COSMAC VIP's CHIP-8 interpreter, for running the high-level
+//! interpreter at VIP speed.
+//!
+//! The costs are the cycle counts from Laurence Scotford's disassembly of the VIP interpreter, in
+//! his "Chip-8 on the COSMAC VIP" series: each instruction pays the fetch and decode loop, then
+//! its own routine. Routines with loops cost more as they go round more often: skips when they
+//! skip, BNNN and FX1E when the address carries into the next page, FX33 for every count it makes
+//! towards each digit, FX55 and FX65 for every register, and DXYN for every row and every bit the
+//! row is shifted. 8XYN costs the same whatever N is, as the interpreter patches N into a single
+//! 1802 ALU instruction and runs all of them through one routine. FX0A sits in the monitor's
+//! keyboard loop until a key comes, which `cpu::run_cycle` counts as the rest of the frame.
+
+use crate::state::State;
+
+/// 1802 machine cycles in one 60 Hz frame of the COSMAC VIP's 1.76 MHz clock.
+static FRAME_CYCLES: u32 = 3668;
+/// Cycles the 1861 takes each frame for display DMA: 8 bytes on each of 128 lines.
+static DISPLAY_DMA_CYCLES: u32 = 1024;
+/// Cycles spent in the interpreter's interrupt routine, which points the display and counts down
+/// the timers.
+static INTERRUPT_CYCLES: u32 = 68;
+/// Cycles left over each frame for CHIP-8 instructions.
+pub static VIP_CYCLES_PER_FRAME: u32 = FRAME_CYCLES - DISPLAY_DMA_CYCLES - INTERRUPT_CYCLES;
+
+/// The interpreter's fetch and decode, paid by every instruction.
+static FETCH_CYCLES: u32 = 40;
+static DRAW_CYCLES_PER_ROW: u32 = 34;
+/// Shifting a sprite row one bit right, done `x % 8` times for each row.
+static DRAW_CYCLES_PER_SHIFT: u32 = 8;
+/// Writing the second display byte of a row that isn't byte-aligned.
+static DRAW_CYCLES_PER_SPLIT_ROW: u32 = 20;
+
+/// Machine cycles the COSMAC VIP interpreter takes to run `opcode`, fetch included, given the state
+/// just before it runs.
+pub fn vip_cycles(state: &State, opcode: u16) -> u32 {
+    let x = state.registers[((opcode & 0x0F00) >> 8) as usize];
+    let y = state.registers[((opcode & 0x00F0) >> 4) as usize];
+    let nn = (opcode & 0x00FF) as u8;
+    let skip = |skipped: bool| if skipped { 4 } else { 0 };
+    let execute = match opcode >> 12 {
+        0x0 if opcode == 0x00E0 => 3078,
+        0x0 if opcode == 0x00EE => 10,
+        0x0 => 0,
+        0x1 => 12,
+        0x2 => 26,
+        0x3 => 10 + skip(x == nn),
+        0x4 => 10 + skip(x != nn),
+        0x5 => 14 + skip(x == y),
+        0x6 => 6,
+        0x7 => 10,
+        // The same routine for every N
+        0x8 => 44,
+        0x9 => 14 + skip(x != y),
+        0xA => 12,
+        0xB => {
+            let offset = if state.quirks.jump { x } else { state.registers[0] };
+            let crosses_page = (opcode & 0x00FF) + offset as u16 > 0xFF;
+            22 + if crosses_page { 2 } else { 0 }
+        }
+        0xC => 36,
+        0xD => draw_cycles(state, x, y, (opcode & 0x000F) as u32),
+        0xE if nn == 0x9E => 14 + skip(state.keypad.is_held(x)),
+        0xE => 14 + skip(!state.keypad.is_held(x)),
+        _ => match nn {
+            0x0A => 16,
+            0x1E => 16 + if (state.index & 0xFF) + x as u32 > 0xFF { 6 } else { 0 },
+            0x29 | 0x30 => 16,
+            0x33 => 80 + 16 * (x / 100 + x / 10 % 10 + x % 10) as u32,
+            0x55 | 0x65 => 14 + 14 * (((opcode & 0x0F00) >> 8) as u32 + 1),
+            _ => 10,
+        },
+    };
+    FETCH_CYCLES + execute
+}
+
+/// DXYN's cost grows with the rows drawn and with how far the sprite sits from a byte boundary.
+fn draw_cycles(state: &State, x: u8, y: u8, height: u32) -> u32 {
+    let screen_height = state.video.len() as u32;
+    let y = y as u32 % screen_height;
+    let rows = if state.quirks.clip { height.min(screen_height - y) } else { height };
+    let shift = (x % 64 % 8) as u32;
+    let split = if shift == 0 { 0 } else { DRAW_CYCLES_PER_SPLIT_ROW };
+    26 + rows * (DRAW_CYCLES_PER_ROW + shift * DRAW_CYCLES_PER_SHIFT + split)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::font::Font;
+    use crate::font::FontSet;
+    use crate::init;
+    use crate::platform::PlatformName;
+
+    fn state() -> State {
+        let platform = PlatformName::Vip.platform();
+        init::init_state(&[0x00, 0xE0], platform, platform.quirks, &Font::Builtin(FontSet::Vip)).unwrap()
+    }
+
+    #[test]
+    fn clears_and_returns() {
+        let state = state();
+        assert_eq!(vip_cycles(&state, 0x00E0), 40 + 3078);
+        assert_eq!(vip_cycles(&state, 0x00EE), 40 + 10);
+    }
+
+    #[test]
+    fn jumps_cost_more_across_a_page() {
+        let mut state = state();
+        assert_eq!(vip_cycles(&state, 0x1234), 40 + 12);
+        assert_eq!(vip_cycles(&state, 0x2234), 40 + 26);
+        state.registers[0] = 0x0F;
+        assert_eq!(vip_cycles(&state, 0xB2F0), 40 + 22);
+        state.registers[0] = 0x10;
+        assert_eq!(vip_cycles(&state, 0xB2F0), 40 + 24);
+    }
+
+    #[test]
+    fn skips_cost_more_when_they_skip() {
+        let mut state = state();
+        state.registers[1] = 5;
+        state.registers[2] = 5;
+        assert_eq!(vip_cycles(&state, 0x3105), 40 + 14);
+        assert_eq!(vip_cycles(&state, 0x3106), 40 + 10);
+        assert_eq!(vip_cycles(&state, 0x4105), 40 + 10);
+        assert_eq!(vip_cycles(&state, 0x4106), 40 + 14);
+        assert_eq!(vip_cycles(&state, 0x5120), 40 + 18);
+        assert_eq!(vip_cycles(&state, 0x9120), 40 + 14);
+        state.keypad.press(5);
+        assert_eq!(vip_cycles(&state, 0xE19E), 40 + 18);
+        assert_eq!(vip_cycles(&state, 0xE1A1), 40 + 14);
+    }
+
+    #[test]
+    fn registers_and_arithmetic() {
+        let state = state();
+        assert_eq!(vip_cycles(&state, 0x6123), 40 + 6);
+        assert_eq!(vip_cycles(&state, 0x7123), 40 + 10);
+        for opcode in [0x8120, 0x8124, 0x8126, 0x812E] {
+            assert_eq!(vip_cycles(&state, opcode), 40 + 44, "{:04X}", opcode);
+        }
+        assert_eq!(vip_cycles(&state, 0xA123), 40 + 12);
+        assert_eq!(vip_cycles(&state, 0xC1FF), 40 + 36);
+    }
+
+    #[test]
+    fn fx_routines_grow_with_their_loops() {
+        let mut state = state();
+        assert_eq!(vip_cycles(&state, 0xF107), 40 + 10);
+        assert_eq!(vip_cycles(&state, 0xF10A), 40 + 16);
+        assert_eq!(vip_cycles(&state, 0xF129), 40 + 16);
+        state.index = 0x2F0;
+        state.registers[1] = 0x0F;
+        assert_eq!(vip_cycles(&state, 0xF11E), 40 + 16);
+        state.registers[1] = 0x10;
+        assert_eq!(vip_cycles(&state, 0xF11E), 40 + 22);
+        state.registers[1] = 0;
+        assert_eq!(vip_cycles(&state, 0xF133), 40 + 80);
+        state.registers[1] = 123;
+        assert_eq!(vip_cycles(&state, 0xF133), 40 + 80 + 16 * 6);
+        assert_eq!(vip_cycles(&state, 0xF055), 40 + 28);
+        assert_eq!(vip_cycles(&state, 0xF365), 40 + 70);
+    }
+
+    #[test]
+    fn draws_cost_more_for_each_row_and_shift() {
+        let mut state = state();
+        assert_eq!(draw_cycles(&state, 8, 0, 5), 26 + 5 * 34);
+        assert_eq!(draw_cycles(&state, 11, 0, 5), 26 + 5 * (34 + 3 * 8 + 20));
+        assert_eq!(draw_cycles(&state, 72, 32, 1), 26 + 34);
+        state.registers[1] = 8;
+        state.registers[2] = 30;
+        state.quirks.clip = true;
+        assert_eq!(vip_cycles(&state, 0xD125), 40 + 26 + 2 * 34);
+        state.quirks.clip = false;
+        assert_eq!(vip_cycles(&state, 0xD125), 40 + 26 + 5 * 34);
+    }
+}
@@ -0,0 +1,436 @@
+//! Execution traces: one entry per executed instruction, describing the machine just before it ran.
+//!
+//! The text format has one line per instruction:
+//!
+//! ```text
+//! PC=0200 OP=6A02 V=00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00 I=0000 DT=00 ST=00 ; LD VA, 0x02
+//! ```
+//!
+//! All numbers are hexadecimal and V lists V0 to VF. Everything after `;` is informational and
+//! ignored when a trace is read back.
+//!
+//! The binary format is a sequence of 24-byte records: PC (2 bytes), opcode (2), V0 to VF (16),
+//! I (2), DT (1) and ST (1), with 16-bit values big-endian.
+//!
+//! When a text trace is read back, the `KEY=value` fields may come in any order and unknown keys
+//! are skipped, so traces from other emulators only need to be massaged into roughly this shape.
+
+use crate::disasm;
+use crate::state::State;
+use clap::ValueEnum;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::ops::RangeInclusive;
+use std::path::Path;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TraceEntry {
+    pub pc: u16,
+    pub opcode: u16,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+}
+
+impl TraceEntry {
+    pub fn capture(state: &State, pc: u16, opcode: u16) -> Self {
+        Self {
+            pc,
+            opcode,
+            registers: state.registers,
+            index: state.index as u16,
+            delay_timer: state.delay_timer,
+            sound_timer: state.sound_timer,
+        }
+    }
+
+    pub fn from_bytes(bytes: &[u8; 24]) -> Self {
+        let mut registers = [0; 16];
+        registers.copy_from_slice(&bytes[4..20]);
+        Self {
+            pc: u16::from_be_bytes([bytes[0], bytes[1]]),
+            opcode: u16::from_be_bytes([bytes[2], bytes[3]]),
+            registers,
+            index: u16::from_be_bytes([bytes[20], bytes[21]]),
+            delay_timer: bytes[22],
+            sound_timer: bytes[23],
+        }
+    }
+
+    /// Parses a text trace line, ignoring the comment after `;`.
+    pub fn parse(line: &str) -> Option<Self> {
+        let fields = line.split(';').next().unwrap_or_default();
+        let (mut pc, mut opcode, mut registers, mut index, mut delay_timer, mut sound_timer) = (None, None, None, None, None, None);
+        for field in fields.split_whitespace() {
+            let (key, value) = field.split_once('=')?;
+            match key.to_ascii_uppercase().as_str() {
+                "PC" => pc = Some(u16::from_str_radix(value, 16).ok()?),
+                "OP" => opcode = Some(u16::from_str_radix(value, 16).ok()?),
+                "V" => {
+                    let values: Vec<u8> = value.split(',').map(|register| u8::from_str_radix(register, 16)).collect::<Result<_, _>>().ok()?;
+                    registers = Some(<[u8; 16]>::try_from(values).ok()?);
+                }
+                "I" => index = Some(u16::from_str_radix(value, 16).ok()?),
+                "DT" => delay_timer = Some(u8::from_str_radix(value, 16).ok()?),
+                "ST" => sound_timer = Some(u8::from_str_radix(value, 16).ok()?),
+                _ => {}
+            }
+        }
+        Some(Self {
+            pc: pc?,
+            opcode: opcode?,
+            registers: registers?,
+            index: index?,
+            delay_timer: delay_timer?,
+            sound_timer: sound_timer?,
+        })
+    }
+
+    /// Names and values, as `(field, ours, theirs)`, of every field that differs from `other`.
+    pub fn differences(&self, other: &TraceEntry) -> Vec<(String, String, String)> {
+        let mut differences = Vec::new();
+        let mut compare = |field: String, ours: u16, theirs: u16, width: usize| {
+            if ours != theirs {
+                differences.push((field, format!("{:01$X}", ours, width), format!("{:01$X}", theirs, width)));
+            }
+        };
+        compare(String::from("PC"), self.pc, other.pc, 4);
+        compare(String::from("OP"), self.opcode, other.opcode, 4);
+        for (register, (ours, theirs)) in self.registers.iter().zip(other.registers.iter()).enumerate() {
+            compare(format!("V{:X}", register), *ours as u16, *theirs as u16, 2);
+        }
+        compare(String::from("I"), self.index, other.index, 4);
+        compare(String::from("DT"), self.delay_timer as u16, other.delay_timer as u16, 2);
+        compare(String::from("ST"), self.sound_timer as u16, other.sound_timer as u16, 2);
+        differences
+    }
+
+    pub fn to_bytes(self) -> [u8; 24] {
+        let mut bytes = [0; 24];
+        bytes[0..2].copy_from_slice(&self.pc.to_be_bytes());
+        bytes[2..4].copy_from_slice(&self.opcode.to_be_bytes());
+        bytes[4..20].copy_from_slice(&self.registers);
+        bytes[20..22].copy_from_slice(&self.index.to_be_bytes());
+        bytes[22] = self.delay_timer;
+        bytes[23] = self.sound_timer;
+        bytes
+    }
+}
+
+impl fmt::Display for TraceEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers: Vec<String> = self.registers.iter().map(|register| format!("{:02X}", register)).collect();
+        write!(
+            f,
+            "PC={:04X} OP={:04X} V={} I={:04X} DT={:02X} ST={:02X} ; {}",
+            self.pc,
+            self.opcode,
+            registers.join(","),
+            self.index,
+            self.delay_timer,
+            self.sound_timer,
+            disasm::disassemble(self.opcode)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TraceFormat {
+    Text,
+    Binary,
+}
+
+#[derive(Debug)]
+pub enum TraceReadError {
+    Io(io::Error),
+    InvalidLine { line_number: usize, line: String },
+    TruncatedRecord { offset: usize },
+}
+
+impl fmt::Display for TraceReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceReadError::Io(error) => write!(f, "could not read trace: {}", error),
+            TraceReadError::InvalidLine { line_number, line } => write!(f, "invalid trace line {}: {:?}", line_number, line),
+            TraceReadError::TruncatedRecord { offset } => write!(f, "trace ends partway through the record at byte {}", offset),
+        }
+    }
+}
+
+impl From<io::Error> for TraceReadError {
+    fn from(error: io::Error) -> Self {
+        TraceReadError::Io(error)
+    }
+}
+
+/// Reads a whole trace file. Blank lines and lines starting with `#` are skipped in text traces.
+pub fn read_trace(path: &Path, format: TraceFormat) -> Result<Vec<TraceEntry>, TraceReadError> {
+    match format {
+        TraceFormat::Text => fs::read_to_string(path)?
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+            .map(|(line_index, line)| {
+                TraceEntry::parse(line).ok_or_else(|| TraceReadError::InvalidLine {
+                    line_number: line_index + 1,
+                    line: line.to_string(),
+                })
+            })
+            .collect(),
+        TraceFormat::Binary => {
+            let bytes = fs::read(path)?;
+            let records = bytes.chunks_exact(24);
+            if !records.remainder().is_empty() {
+                return Err(TraceReadError::TruncatedRecord {
+                    offset: bytes.len() - records.remainder().len(),
+                });
+            }
+            Ok(records.map(|record| TraceEntry::from_bytes(record.try_into().unwrap())).collect())
+        }
+    }
+}
+
+/// Index of the first entry at which the traces disagree, including one trace ending early.
+pub fn first_difference(ours: &[TraceEntry], theirs: &[TraceEntry]) -> Option<usize> {
+    ours.iter()
+        .zip(theirs.iter())
+        .position(|(ours, theirs)| ours != theirs)
+        .or_else(|| (ours.len() != theirs.len()).then(|| ours.len().min(theirs.len())))
+}
+
+/// Which instructions get traced. An empty filter traces everything.
+#[derive(Debug, Clone, Default)]
+pub struct TraceFilter {
+    addresses: Option<RangeInclusive<u16>>,
+    /// Opcode patterns such as `DXYN` or mnemonics such as `DRW`, upper-cased to match `disasm`.
+    instructions: Vec<String>,
+}
+
+impl TraceFilter {
+    /// A filter for instructions in `addresses` that match one of `instructions`, in any case.
+    pub fn new(addresses: Option<RangeInclusive<u16>>, instructions: &[String]) -> Self {
+        Self {
+            addresses,
+            instructions: instructions.iter().map(|instruction| instruction.to_ascii_uppercase()).collect(),
+        }
+    }
+
+    pub fn matches(&self, pc: u16, opcode: u16) -> bool {
+        let address_matches = self.addresses.as_ref().is_none_or(|addresses| addresses.contains(&pc));
+        let instruction_matches = self.instructions.is_empty() || {
+            let (pattern, mnemonic) = (disasm::pattern(opcode), disasm::mnemonic(opcode));
+            self.instructions.iter().any(|instruction| instruction == pattern || instruction == mnemonic)
+        };
+        address_matches && instruction_matches
+    }
+}
+
+/// Writes trace entries to a file, either as they happen or, in ring buffer mode, only the last
+/// few when `dump` is called after a fault. A buffer can instead keep the first few, for comparing
+/// against a reference trace of known length.
+#[derive(Debug)]
+pub struct Tracer {
+    path: PathBuf,
+    format: TraceFormat,
+    filter: TraceFilter,
+    writer: Option<BufWriter<File>>,
+    ring: Option<(usize, VecDeque<TraceEntry>)>,
+    /// Whether a full buffer keeps its oldest entries and drops new ones.
+    keep_first: bool,
+}
+
+impl Tracer {
+    /// Starts a trace that is written as the program runs.
+    pub fn to_file(path: &Path, format: TraceFormat, filter: TraceFilter) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            format,
+            filter,
+            writer: Some(BufWriter::new(File::create(path)?)),
+            ring: None,
+            keep_first: false,
+        })
+    }
+
+    /// Starts a trace that keeps only the last `capacity` entries in memory until `dump` is called.
+    pub fn ring_buffer(path: &Path, format: TraceFormat, filter: TraceFilter, capacity: usize) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            format,
+            filter,
+            writer: None,
+            ring: Some((capacity.max(1), VecDeque::with_capacity(capacity.max(1)))),
+            keep_first: false,
+        }
+    }
+
+    /// Starts a trace that keeps only the first `count` entries in memory until `dump` is called.
+    pub fn first_entries(path: &Path, format: TraceFormat, filter: TraceFilter, count: usize) -> Self {
+        Self {
+            keep_first: true,
+            ..Self::ring_buffer(path, format, filter, count)
+        }
+    }
+
+    pub fn record(&mut self, state: &State, pc: u16, opcode: u16) {
+        if !self.filter.matches(pc, opcode) {
+            return;
+        }
+        let entry = TraceEntry::capture(state, pc, opcode);
+        if let Some((capacity, entries)) = &mut self.ring {
+            if entries.len() == *capacity {
+                if self.keep_first {
+                    return;
+                }
+                entries.pop_front();
+            }
+            entries.push_back(entry);
+        } else if let Some(writer) = &mut self.writer {
+            if let Err(error) = write_entry(writer, self.format, &entry) {
+                eprintln!("Stopped tracing to {}: {}", self.path.display(), error);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Pushes buffered lines of a streaming trace to disk so they survive the process being killed.
+    pub fn flush(&mut self) {
+        if let Some(writer) = &mut self.writer {
+            if let Err(error) = writer.flush() {
+                eprintln!("Stopped tracing to {}: {}", self.path.display(), error);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// Writes out the ring buffer, oldest entry first, or flushes a streaming trace.
+    pub fn dump(&mut self) -> io::Result<()> {
+        if let Some((_, entries)) = &self.ring {
+            let mut writer = BufWriter::new(File::create(&self.path)?);
+            for entry in entries {
+                write_entry(&mut writer, self.format, entry)?;
+            }
+            writer.flush()
+        } else if let Some(writer) = &mut self.writer {
+            writer.flush()
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Entries held in ring buffer mode, oldest first.
+    pub fn entries(&self) -> Vec<TraceEntry> {
+        self.ring.iter().flat_map(|(_, entries)| entries.iter().copied()).collect()
+    }
+
+    /// Number of entries held in ring buffer mode.
+    pub fn recorded(&self) -> usize {
+        self.ring.as_ref().map_or(0, |(_, entries)| entries.len())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+fn write_entry(writer: &mut impl Write, format: TraceFormat, entry: &TraceEntry) -> io::Result<()> {
+    match format {
+        TraceFormat::Text => writeln!(writer, "{}", entry),
+        TraceFormat::Binary => writer.write_all(&entry.to_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/trace.txt");
+
+    fn entry(pc: u16, opcode: u16) -> TraceEntry {
+        TraceEntry {
+            pc,
+            opcode,
+            registers: [0; 16],
+            index: 0,
+            delay_timer: 0,
+            sound_timer: 0,
+        }
+    }
+
+    #[test]
+    fn reads_a_text_trace() {
+        let entries = read_trace(Path::new(FIXTURE), TraceFormat::Text).unwrap();
+        assert_eq!(entries.len(), 6);
+        assert_eq!((entries[3].pc, entries[3].opcode, entries[3].registers[0], entries[3].index), (0x206, 0xF01E, 0x06, 0x20A));
+    }
+
+    #[test]
+    fn parses_fields_in_any_order_and_skips_unknown_keys() {
+        let line = "op=00e0 CYCLES=12 pc=0200 DT=3C ST=00 I=0123 V=01,02,03,04,05,06,07,08,09,0A,0B,0C,0D,0E,0F,10";
+        let parsed = TraceEntry::parse(line).unwrap();
+        assert_eq!((parsed.pc, parsed.opcode, parsed.index, parsed.delay_timer), (0x200, 0x00E0, 0x123, 0x3C));
+        assert_eq!(parsed.registers[15], 0x10);
+    }
+
+    #[test]
+    fn rejects_incomplete_lines() {
+        assert_eq!(TraceEntry::parse("PC=0200 OP=00E0"), None);
+        assert_eq!(TraceEntry::parse("PC=0200 OP=00E0 V=00,00 I=0000 DT=00 ST=00"), None);
+        assert_eq!(TraceEntry::parse("PC=zz OP=00E0 V=00,00,00,00,00,00,00,00,00,00,00,00,00,00,00,00 I=0000 DT=00 ST=00"), None);
+    }
+
+    #[test]
+    fn text_lines_read_back_as_written() {
+        let written = TraceEntry {
+            registers: [0xAB; 16],
+            index: 0xFFF,
+            sound_timer: 7,
+            ..entry(0x2FE, 0xD015)
+        };
+        assert_eq!(TraceEntry::parse(&written.to_string()), Some(written));
+    }
+
+    #[test]
+    fn binary_records_read_back_as_written() {
+        let entries = read_trace(Path::new(FIXTURE), TraceFormat::Text).unwrap();
+        let path = std::env::temp_dir().join(format!("chip8-trace-test-{}.bin", std::process::id()));
+        let mut bytes: Vec<u8> = entries.iter().flat_map(|entry| entry.to_bytes()).collect();
+        fs::write(&path, &bytes).unwrap();
+        assert_eq!(read_trace(&path, TraceFormat::Binary).unwrap(), entries);
+
+        bytes.pop();
+        fs::write(&path, &bytes).unwrap();
+        let truncated = read_trace(&path, TraceFormat::Binary);
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(truncated, Err(TraceReadError::TruncatedRecord { offset: 120 })));
+    }
+
+    #[test]
+    fn finds_the_first_difference() {
+        let ours = [entry(0x200, 0x6005), entry(0x202, 0x7001), entry(0x204, 0x1204)];
+        let mut theirs = ours;
+        assert_eq!(first_difference(&ours, &theirs), None);
+        assert_eq!(first_difference(&ours, &theirs[..2]), Some(2));
+        theirs[1].registers[3] = 9;
+        assert_eq!(first_difference(&ours, &theirs), Some(1));
+        assert_eq!(ours[1].differences(&theirs[1]), vec![(String::from("V3"), String::from("00"), String::from("09"))]);
+    }
+
+    #[test]
+    fn filters_by_address_pattern_and_mnemonic() {
+        let filter = TraceFilter::new(Some(0x200..=0x20F), &[String::from("dxyn"), String::from("Ld")]);
+        assert!(filter.matches(0x200, 0xD125));
+        assert!(filter.matches(0x202, 0x6005));
+        assert!(filter.matches(0x204, 0xF065));
+        assert!(!filter.matches(0x206, 0x7001));
+        assert!(!filter.matches(0x210, 0xD125));
+        assert!(TraceFilter::default().matches(0xFFE, 0x0000));
+    }
+}
@@ -1,57 +1,275 @@
-use crate::cpu;
+#[cfg(feature = "audio")]
+use crate::audio::Speaker;
+use crate::capture;
+use crate::capture::CaptureError;
+use crate::capture::GifRecorder;
+use crate::cartridge;
+use crate::cli;
+use crate::cli::Args;
+use crate::cli::MachineArgs;
+use crate::coverage::Coverage;
+use crate::cpu::Halt;
+use crate::cpu::Hooks;
+use crate::disasm;
+use crate::font;
+use crate::font::FontSet;
 use crate::init;
+use crate::init::RomError;
+use crate::keymap::Keymap;
+use crate::machine::ColourFrame;
+use crate::machine::Machine;
+use crate::octo::OctoOptions;
+use crate::palette::Palette;
+use crate::platform::Platform;
+use crate::platform::PlatformName;
+use crate::platform::MAX_CYCLES_PER_TICK;
+use crate::profiler::Profiler;
+use crate::recent;
+use crate::recent::RecentRom;
+use crate::rotation::Rotation;
+use crate::state::pixel_bitmask;
+use crate::state::KeyWait;
+use crate::state::Quirks;
+use crate::state::StackDepth;
+use crate::state::StackPolicy;
 use crate::state::State;
+use crate::state::Timing;
+use crate::strict::StrictMode;
+use crate::trace::Tracer;
+use crate::vip::Vip;
+use crate::vip::VipImages;
+use iced::event;
 use iced::keyboard;
-use iced::mouse;
 use iced::widget::button;
-use iced::widget::canvas::Geometry;
+use iced::widget::checkbox;
+use iced::widget::column;
+use iced::widget::container;
+use iced::widget::image;
+use iced::widget::image::FilterMethod;
+use iced::widget::pick_list;
 use iced::widget::row;
-use iced::widget::Canvas;
+use iced::widget::scrollable;
+use iced::widget::Column;
 use iced::widget::Container;
+use iced::widget::Image;
+use iced::widget::Space;
 use iced::widget::Text;
-use iced::widget::{canvas, column};
+use iced::window;
 use iced::Color;
-use iced::Point;
-use iced::Rectangle;
-use iced::Renderer;
+use iced::ContentFit;
+use iced::Event;
+use iced::Font;
 use iced::Size;
 use iced::{executor, time, Application, Command, Element, Length, Settings, Theme};
 use iced_aw::number_input;
 use iced_aw::NumberInputStyles;
 use rfd::FileDialog;
+use rfd::MessageButtons;
+use rfd::MessageDialog;
+use rfd::MessageLevel;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
 use std::time::Duration;
+use std::time::Instant;
+use std::time::SystemTime;
+
+/// Warnings kept for the warnings panel before the oldest are dropped.
+static MAX_WARNINGS: usize = 1000;
+/// Approximate height of the controls above the display, used to size the window for `--scale`.
+static TOOLBAR_HEIGHT: f32 = 40.;
 
 #[derive(Debug)]
 struct Chip8Emu {
     state: Option<State>,
-    cycles_per_tick: u8,
+    /// Runs the ROM instead of `state` when the COSMAC VIP images were given.
+    vip: Option<Vip>,
+    vip_images: Option<VipImages>,
+    rom: Option<LoadedRom>,
+    recent_roms: Vec<RecentRom>,
+    cycles_per_tick: u16,
     ticks_per_second: u8,
     paused: bool,
+    fast_forward: bool,
+    fast_forward_multiplier: u8,
+    slow_motion_divisor: u8,
     canvas: Chip8EmuCanvas,
+    capture_scale: u8,
+    recorder: Option<GifRecorder>,
+    #[cfg(feature = "audio")]
+    speaker: Option<Speaker>,
+    status: String,
+    keymap: Keymap,
+    platform: Platform,
+    /// Machine settings from the command line, which win over each platform's own.
+    machine: MachineArgs,
+    ips: Option<u32>,
+    quirks: Quirks,
+    font: font::Font,
+    stack_policy: StackPolicy,
+    strict_mode: bool,
+    strict_pause: bool,
+    /// Most recent warnings, oldest first.
+    warnings: VecDeque<String>,
+    window_scale: f32,
+    hooks: Hooks,
+    panel: Panel,
+    /// Which 256 bytes the memory panel shows.
+    memory_page: u16,
+    /// Colours and rotation picked on the command line or in the toolbar, which a ROM's Octo
+    /// options override only while that ROM is loaded.
+    palette: Palette,
+    rotation: Rotation,
+}
+
+/// The ROM currently in the machine, kept so it can be reset without touching the disk.
+#[derive(Debug)]
+struct LoadedRom {
+    path: PathBuf,
+    bytes: Vec<u8>,
 }
 
 #[derive(Debug)]
 struct Chip8EmuFlags {
-    cycles_per_tick: u8,
+    cycles_per_tick: u16,
     ticks_per_second: u8,
+    rom_path: Option<PathBuf>,
+    paused: bool,
+    palette: Palette,
+    fullscreen: bool,
+    keymap: Keymap,
+    window_scale: f32,
+    tracer: Option<Tracer>,
+    platform: Platform,
+    machine: MachineArgs,
+    vip_images: Option<VipImages>,
+    ips: Option<u32>,
+    quirks: Quirks,
+    font: font::Font,
+    stack_policy: StackPolicy,
+    strict_mode: bool,
+    strict_pause: bool,
 }
 
 #[derive(Debug, Clone)]
 pub enum Message {
     GameTick,
     TogglePause,
+    FastForward(bool),
+    FrameAdvance,
+    SetFastForwardMultiplier(u8),
+    SetSlowMotionDivisor(u8),
+    KeyPressed(keyboard::Key, keyboard::Modifiers),
+    KeyReleased(keyboard::Key),
     KeyDown(u8),
     KeyUp(u8),
     LoadRom,
-    SetCyclesPerTick(u8),
+    FileDropped(PathBuf),
+    LoadRecentRom(RecentRom),
+    OpenRecentRomAt(usize),
+    ResetRom,
+    ReloadRom,
+    CloseRom,
+    ExportCartridge,
+    SetPlatform(PlatformName),
+    SetQuirks(Quirks),
+    SetFontSet(FontSet),
+    LoadFont,
+    SetStackPolicy(StackPolicy),
+    SetStrictMode(bool),
+    SetStrictPause(bool),
+    SetCyclesPerTick(u16),
     SetTicksPerSecond(u8),
+    SetRenderMode(RenderMode),
+    SetPersistenceFrames(u8),
+    SetPalette(Palette),
+    SetRotation(Rotation),
+    SetCaptureScale(u8),
+    Screenshot,
+    ToggleRecording,
+    SetPanel(Panel),
+    SetMemoryPage(u16),
+}
+
+/// Tool shown beside the display.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Panel {
+    #[default]
+    Hidden,
+    /// Disassembly of everything executed, shaded by how often it ran.
+    Profiler,
+    /// Hex dump of memory, coloured by how each byte has been used.
+    Memory,
+    /// Stack and strict mode warnings.
+    Warnings,
+}
+
+impl Panel {
+    const ALL: [Panel; 4] = [Panel::Hidden, Panel::Profiler, Panel::Memory, Panel::Warnings];
+}
+
+impl fmt::Display for Panel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Panel::Hidden => write!(f, "None"),
+            Panel::Profiler => write!(f, "Profiler"),
+            Panel::Memory => write!(f, "Memory"),
+            Panel::Warnings => write!(f, "Warnings"),
+        }
+    }
+}
+
+/// How the canvas turns the emulated framebuffer into pixels on screen.
+/// The persistence modes only affect what is drawn, never `State::video`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RenderMode {
+    /// Draw exactly the current framebuffer.
+    #[default]
+    Direct,
+    /// Keep pixels lit after they are erased and fade them out over the persistence frames, like a CRT phosphor.
+    Fade,
+    /// Average the last persistence frames together.
+    Blend,
+}
+
+impl RenderMode {
+    const ALL: [RenderMode; 3] = [RenderMode::Direct, RenderMode::Fade, RenderMode::Blend];
+}
+
+impl fmt::Display for RenderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RenderMode::Direct => write!(f, "Direct"),
+            RenderMode::Fade => write!(f, "Phosphor fade"),
+            RenderMode::Blend => write!(f, "Frame blend"),
+        }
+    }
 }
 
 impl Default for Chip8EmuFlags {
     fn default() -> Self {
         Self {
-            cycles_per_tick: 4,
+            cycles_per_tick: Platform::default().cycles_per_tick,
             ticks_per_second: 60,
+            rom_path: None,
+            paused: false,
+            palette: Palette::default(),
+            fullscreen: false,
+            keymap: Keymap::default(),
+            window_scale: 10.,
+            tracer: None,
+            platform: Platform::default(),
+            machine: MachineArgs::default(),
+            vip_images: None,
+            ips: None,
+            quirks: Quirks::default(),
+            font: font::Font::default(),
+            stack_policy: StackPolicy::default(),
+            strict_mode: false,
+            strict_pause: false,
         }
     }
 }
@@ -63,66 +281,74 @@ impl Application for Chip8Emu {
     type Flags = Chip8EmuFlags;
 
     fn new(flags: Self::Flags) -> (Self, iced::Command<Self::Message>) {
-        (
-            Self {
-                state: None,
-                cycles_per_tick: flags.cycles_per_tick,
-                ticks_per_second: flags.ticks_per_second,
-                paused: true,
-                canvas: Chip8EmuCanvas::default(),
+        let mut emu = Self {
+            state: None,
+            vip: None,
+            vip_images: flags.vip_images,
+            rom: None,
+            recent_roms: recent::load_recent_roms(),
+            cycles_per_tick: flags.cycles_per_tick,
+            ticks_per_second: flags.ticks_per_second,
+            paused: true,
+            fast_forward: false,
+            fast_forward_multiplier: 4,
+            slow_motion_divisor: 1,
+            canvas: Chip8EmuCanvas::default(),
+            capture_scale: 10,
+            recorder: None,
+            #[cfg(feature = "audio")]
+            speaker: Speaker::open(),
+            status: String::new(),
+            keymap: flags.keymap,
+            platform: flags.platform,
+            machine: flags.machine,
+            ips: flags.ips,
+            quirks: flags.quirks,
+            font: flags.font,
+            stack_policy: flags.stack_policy,
+            strict_mode: flags.strict_mode,
+            strict_pause: flags.strict_pause,
+            warnings: VecDeque::new(),
+            window_scale: flags.window_scale,
+            hooks: Hooks {
+                tracer: flags.tracer,
+                ..Hooks::default()
             },
-            Command::none(),
-        )
+            panel: Panel::Hidden,
+            memory_page: 2,
+            palette: flags.palette,
+            rotation: Rotation::default(),
+        };
+        emu.canvas.palette = flags.palette;
+        let mut commands = Vec::new();
+        if let Some(rom_path) = flags.rom_path {
+            commands.push(emu.load_rom_file(rom_path));
+            emu.paused = flags.paused || emu.machine().is_none();
+        }
+        if flags.fullscreen {
+            commands.push(window::change_mode(window::Id::MAIN, window::Mode::Fullscreen));
+        }
+        (emu, Command::batch(commands))
     }
 
     fn title(&self) -> String {
-        String::from("Chip-8 Emulator")
+        match &self.rom {
+            Some(rom) => format!("Chip-8 Emulator - {}", rom.path.file_name().unwrap_or_default().to_string_lossy()),
+            None => String::from("Chip-8 Emulator"),
+        }
     }
 
     fn subscription(&self) -> iced::Subscription<Self::Message> {
         iced::Subscription::batch(vec![
             if !self.paused {
-                time::every(Duration::from_secs_f64(1. / (self.ticks_per_second as f64))).map(|_| Self::Message::GameTick)
+                time::every(self.tick_interval()).map(|_| Self::Message::GameTick)
             } else {
                 iced::Subscription::none()
             },
-            keyboard::on_key_press(|key, _modifiers| match key.as_ref() {
-                keyboard::key::Key::Character("0") => Some(Message::KeyDown(0)),
-                keyboard::key::Key::Character("1") => Some(Message::KeyDown(1)),
-                keyboard::key::Key::Character("2") => Some(Message::KeyDown(2)),
-                keyboard::key::Key::Character("3") => Some(Message::KeyDown(3)),
-                keyboard::key::Key::Character("Q") => Some(Message::KeyDown(4)),
-                keyboard::key::Key::Character("W") => Some(Message::KeyDown(5)),
-                keyboard::key::Key::Character("E") => Some(Message::KeyDown(6)),
-                keyboard::key::Key::Character("R") => Some(Message::KeyDown(7)),
-                keyboard::key::Key::Character("A") => Some(Message::KeyDown(8)),
-                keyboard::key::Key::Character("S") => Some(Message::KeyDown(9)),
-                keyboard::key::Key::Character("D") => Some(Message::KeyDown(10)),
-                keyboard::key::Key::Character("F") => Some(Message::KeyDown(11)),
-                keyboard::key::Key::Character("Z") => Some(Message::KeyDown(12)),
-                keyboard::key::Key::Character("X") => Some(Message::KeyDown(13)),
-                keyboard::key::Key::Character("C") => Some(Message::KeyDown(14)),
-                keyboard::key::Key::Character("V") => Some(Message::KeyDown(15)),
-                _ => None,
-            }),
-            keyboard::on_key_release(|key, _modifiers| match key.as_ref() {
-                keyboard::key::Key::Character("0") => Some(Message::KeyUp(0)),
-                keyboard::key::Key::Character("1") => Some(Message::KeyUp(1)),
-                keyboard::key::Key::Character("2") => Some(Message::KeyUp(2)),
-                keyboard::key::Key::Character("3") => Some(Message::KeyUp(3)),
-                keyboard::key::Key::Character("Q") => Some(Message::KeyUp(4)),
-                keyboard::key::Key::Character("W") => Some(Message::KeyUp(5)),
-                keyboard::key::Key::Character("E") => Some(Message::KeyUp(6)),
-                keyboard::key::Key::Character("R") => Some(Message::KeyUp(7)),
-                keyboard::key::Key::Character("A") => Some(Message::KeyUp(8)),
-                keyboard::key::Key::Character("S") => Some(Message::KeyUp(9)),
-                keyboard::key::Key::Character("D") => Some(Message::KeyUp(10)),
-                keyboard::key::Key::Character("F") => Some(Message::KeyUp(11)),
-                keyboard::key::Key::Character("Z") => Some(Message::KeyUp(12)),
-                keyboard::key::Key::Character("X") => Some(Message::KeyUp(13)),
-                keyboard::key::Key::Character("C") => Some(Message::KeyUp(14)),
-                keyboard::key::Key::Character("V") => Some(Message::KeyUp(15)),
-                keyboard::key::Key::Named(keyboard::key::Named::Space) => Some(Message::TogglePause),
+            keyboard::on_key_press(|key, modifiers| Some(Message::KeyPressed(key, modifiers))),
+            keyboard::on_key_release(|key, _modifiers| Some(Message::KeyReleased(key))),
+            event::listen_with(|event, _status| match event {
+                Event::Window(_, window::Event::FileDropped(path)) => Some(Message::FileDropped(path)),
                 _ => None,
             }),
         ])
@@ -130,51 +356,258 @@ impl Application for Chip8Emu {
 
     fn update(&mut self, message: Self::Message) -> iced::Command<Self::Message> {
         match message {
-            Message::GameTick => match &mut self.state {
-                Some(state) => {
-                    if !self.paused {
-                        cpu::run_cycle(state, self.cycles_per_tick);
-                        if state.video != self.canvas.video {
-                            self.canvas.video = state.video;
-                            self.canvas.canvas_cache.clear();
+            Message::GameTick => {
+                if !self.paused {
+                    if !self.fast_forward {
+                        self.run_frame();
+                    } else if self.fast_forward_multiplier == 0 {
+                        // Uncapped: run whole frames for most of the tick, leaving time to draw
+                        let started = Instant::now();
+                        let budget = self.tick_interval().mul_f64(0.8);
+                        while started.elapsed() < budget && !self.paused && self.machine().is_some() {
+                            self.run_frame();
+                        }
+                    } else {
+                        // A halt or fault pauses part way through
+                        for _ in 0..self.fast_forward_multiplier {
+                            if self.paused {
+                                break;
+                            }
+                            self.run_frame();
                         }
                     }
                 }
-                None => {}
-            },
+            }
             Message::TogglePause => {
                 self.paused = !self.paused;
             }
-            Message::KeyDown(key_num) => match &mut self.state {
-                Some(state) => {
-                    state.keypad[key_num as usize] = true;
+            Message::FastForward(fast_forward) => {
+                self.fast_forward = fast_forward;
+            }
+            Message::FrameAdvance => {
+                if self.paused {
+                    self.run_frame();
                 }
-                None => {}
-            },
-            Message::KeyUp(key_num) => match &mut self.state {
-                Some(state) => {
-                    state.keypad[key_num as usize] = false;
+            }
+            Message::SetFastForwardMultiplier(fast_forward_multiplier) => {
+                self.fast_forward_multiplier = fast_forward_multiplier;
+            }
+            Message::SetSlowMotionDivisor(slow_motion_divisor) => {
+                self.slow_motion_divisor = slow_motion_divisor.max(1);
+            }
+            Message::KeyPressed(key, modifiers) => {
+                let message = if modifiers.command() {
+                    rom_shortcut(&key, modifiers)
+                } else if let Some(key_num) = self.keymap.keypad_key(&key) {
+                    Some(Message::KeyDown(key_num))
+                } else {
+                    match key.as_ref() {
+                        keyboard::key::Key::Named(keyboard::key::Named::F12) => Some(Message::Screenshot),
+                        keyboard::key::Key::Named(keyboard::key::Named::F9) => Some(Message::ToggleRecording),
+                        keyboard::key::Key::Named(keyboard::key::Named::Tab) => Some(Message::FastForward(true)),
+                        keyboard::key::Key::Character(".") => Some(Message::FrameAdvance),
+                        _ => None,
+                    }
+                };
+                if let Some(message) = message {
+                    return self.update(message);
                 }
-                None => {}
-            },
+            }
+            Message::KeyReleased(key) => {
+                let message = if let Some(key_num) = self.keymap.keypad_key(&key) {
+                    Some(Message::KeyUp(key_num))
+                } else {
+                    match key.as_ref() {
+                        keyboard::key::Key::Named(keyboard::key::Named::Space) => Some(Message::TogglePause),
+                        keyboard::key::Key::Named(keyboard::key::Named::Tab) => Some(Message::FastForward(false)),
+                        _ => None,
+                    }
+                };
+                if let Some(message) = message {
+                    return self.update(message);
+                }
+            }
+            Message::KeyDown(key_num) => {
+                if let Some(machine) = self.machine_mut() {
+                    match key_num {
+                        0..=15 => machine.keypad().press(key_num),
+                        _ => machine.second_keypad().into_iter().for_each(|keypad| keypad.press(key_num - 16)),
+                    }
+                }
+            }
+            Message::KeyUp(key_num) => {
+                if let Some(machine) = self.machine_mut() {
+                    match key_num {
+                        0..=15 => machine.keypad().release(key_num),
+                        _ => machine.second_keypad().into_iter().for_each(|keypad| keypad.release(key_num - 16)),
+                    }
+                }
+            }
             Message::LoadRom => {
                 let rom_path = FileDialog::new()
-                    .add_filter("CHIP-8 ROM", &["ch8", "CH8"])
+                    .add_filter("CHIP-8 ROM", &init::ROM_EXTENSIONS)
                     .pick_file();
-                match rom_path {
-                    Some(rom_path) => {
-                        self.state = Some(init::init_state(rom_path.as_path()));
-                        self.paused = false;
+                if let Some(rom_path) = rom_path {
+                    return self.load_rom_file(rom_path);
+                }
+            }
+            Message::FileDropped(rom_path) => {
+                if init::has_rom_extension(&rom_path) {
+                    return self.load_rom_file(rom_path);
+                } else {
+                    self.status = format!("{} is not a CHIP-8 ROM", rom_path.display());
+                }
+            }
+            Message::LoadRecentRom(recent_rom) => {
+                return self.load_rom_file(recent_rom.0);
+            }
+            Message::OpenRecentRomAt(position) => {
+                if let Some(recent_rom) = self.recent_roms.get(position - 1) {
+                    return self.load_rom_file(recent_rom.0.clone());
+                }
+            }
+            Message::ResetRom => {
+                if let Some((rom_path, rom_bytes)) = self.rom.as_ref().map(|rom| (rom.path.clone(), rom.bytes.clone())) {
+                    match self.start_machine(&rom_bytes) {
+                        Ok(()) => {
+                            self.canvas.reset();
+                            self.reset_instrumentation();
+                            self.paused = false;
+                            self.status = String::from("Reset");
+                        }
+                        Err(error) => show_rom_error(&rom_path, &error),
+                    }
+                }
+            }
+            Message::ReloadRom => {
+                if let Some(rom_path) = self.rom.as_ref().map(|rom| rom.path.clone()) {
+                    return self.load_rom_file(rom_path);
+                }
+            }
+            Message::SetStackPolicy(stack_policy) => {
+                self.stack_policy = stack_policy;
+                if let Some(state) = &mut self.state {
+                    state.stack_policy = stack_policy;
+                }
+            }
+            Message::SetStrictMode(strict_mode) => {
+                self.strict_mode = strict_mode;
+                self.hooks.strict = self.state.as_ref().filter(|_| strict_mode).map(|state| StrictMode::new(state, self.rom_size(), self.strict_pause));
+            }
+            Message::SetStrictPause(strict_pause) => {
+                self.strict_pause = strict_pause;
+                if let Some(strict) = &mut self.hooks.strict {
+                    strict.pause = strict_pause;
+                }
+            }
+            Message::SetPlatform(platform_name) => {
+                self.machine.platform = Some(platform_name);
+                let command = self.set_platform(platform_name.platform());
+                return Command::batch([command, self.update(Message::ResetRom)]);
+            }
+            Message::SetQuirks(quirks) => {
+                self.quirks = quirks;
+                if let Some(state) = &mut self.state {
+                    state.quirks = quirks;
+                }
+            }
+            Message::SetFontSet(font_set) => {
+                self.set_font(font::Font::Builtin(font_set));
+            }
+            Message::LoadFont => {
+                let font_path = FileDialog::new().add_filter("Font binary", &["bin", "fnt"]).pick_file();
+                if let Some(font_path) = font_path {
+                    match font::Font::from_file(&font_path) {
+                        Ok(font) => self.set_font(font),
+                        Err(error) => self.status = format!("{}: {}", font_path.display(), error),
                     }
-                    None => {}
                 }
             }
+            Message::ExportCartridge => {
+                self.export_cartridge();
+            }
+            Message::CloseRom => {
+                self.state = None;
+                self.vip = None;
+                self.rom = None;
+                self.recorder = None;
+                self.canvas.reset();
+                self.reset_instrumentation();
+                self.paused = true;
+                self.status = String::new();
+            }
             Message::SetCyclesPerTick(cycles_per_tick) => {
                 self.cycles_per_tick = cycles_per_tick;
             }
             Message::SetTicksPerSecond(ticks_per_second) => {
                 self.ticks_per_second = ticks_per_second;
             }
+            Message::SetPanel(panel) => {
+                self.panel = panel;
+                if panel == Panel::Profiler && self.hooks.profiler.is_none() {
+                    self.hooks.profiler = Some(Profiler::default());
+                }
+                if panel == Panel::Memory && self.hooks.coverage.is_none() {
+                    self.hooks.coverage = Some(Coverage::new(self.platform.memory_size));
+                }
+            }
+            Message::SetMemoryPage(memory_page) => {
+                self.memory_page = memory_page;
+            }
+            Message::SetRenderMode(render_mode) => {
+                self.canvas.render_mode = render_mode;
+                self.canvas.reset_persistence();
+            }
+            Message::SetPersistenceFrames(persistence_frames) => {
+                self.canvas.persistence_frames = persistence_frames.max(1);
+                self.canvas.reset_persistence();
+            }
+            Message::SetPalette(palette) => {
+                self.palette = palette;
+                self.canvas.palette = palette;
+                self.canvas.reset_persistence();
+            }
+            Message::SetRotation(rotation) => {
+                self.rotation = rotation;
+                return self.set_rotation(rotation);
+            }
+            Message::SetCaptureScale(capture_scale) => {
+                self.capture_scale = capture_scale.max(1);
+            }
+            Message::Screenshot => {
+                if let Some(machine) = self.machine() {
+                    let path = capture_path("screenshot", "png");
+                    let saved = match machine.colour_video() {
+                        Some(frame) => capture::save_colour_screenshot(&path, &frame, self.capture_scale as u32, self.canvas.rotation),
+                        None => capture::save_screenshot(&path, machine.video(), machine.display_width(), self.capture_scale as u32, self.canvas.palette, self.canvas.rotation),
+                    };
+                    self.status = match saved {
+                        Ok(()) => format!("Saved {}", path.display()),
+                        Err(error) => error.to_string(),
+                    };
+                }
+            }
+            Message::ToggleRecording => {
+                if self.recorder.take().is_some() {
+                    self.status = String::from("Recording stopped");
+                } else if self.machine().is_some() {
+                    let path = capture_path("recording", "gif");
+                    match GifRecorder::create(
+                        &path,
+                        self.platform.window_size(),
+                        self.capture_scale as u32,
+                        self.canvas.palette,
+                        self.canvas.rotation,
+                        self.ticks_per_second as f64,
+                    ) {
+                        Ok(recorder) => {
+                            self.recorder = Some(recorder);
+                            self.status = format!("Recording to {}", path.display());
+                        }
+                        Err(error) => self.status = error.to_string(),
+                    }
+                }
+            }
         }
         Command::none()
     }
@@ -183,10 +616,15 @@ impl Application for Chip8Emu {
         column![
             row([
                 button("Load Rom").padding([5, 10]).on_press(Message::LoadRom).into(),
+                button("Reset").padding([5, 10]).on_press_maybe(self.rom.as_ref().map(|_| Message::ResetRom)).into(),
+                button("Reload").padding([5, 10]).on_press_maybe(self.rom.as_ref().map(|_| Message::ReloadRom)).into(),
+                button("Close").padding([5, 10]).on_press_maybe(self.rom.as_ref().map(|_| Message::CloseRom)).into(),
+                button("Export Cartridge").padding([5, 10]).on_press_maybe(self.rom.as_ref().map(|_| Message::ExportCartridge)).into(),
+                pick_list(self.recent_roms.as_slice(), None::<RecentRom>, Message::LoadRecentRom).placeholder("Recent ROMs").into(),
                 Container::new(Text::new("Cycles per tick:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
-                number_input(self.cycles_per_tick, 255, Message::SetCyclesPerTick)
+                number_input(self.cycles_per_tick, MAX_CYCLES_PER_TICK, Message::SetCyclesPerTick)
                     .style(NumberInputStyles::Default)
-                    .width(Length::Fixed(60.))
+                    .width(Length::Fixed(75.))
                     .step(1)
                     .into(),
                 Container::new(Text::new("Ticks per second:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
@@ -195,65 +633,727 @@ impl Application for Chip8Emu {
                     .width(Length::Fixed(60.))
                     .step(10)
                     .into(),
+                Container::new(Text::new("Fast-forward x (0 = uncapped):"))
+                    .height(Length::Fill)
+                    .padding([0, 0, 0, 25])
+                    .center_y()
+                    .into(),
+                number_input(self.fast_forward_multiplier, 16, Message::SetFastForwardMultiplier)
+                    .style(NumberInputStyles::Default)
+                    .width(Length::Fixed(60.))
+                    .step(1)
+                    .into(),
+                Container::new(Text::new("Slow-motion 1/")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                number_input(self.slow_motion_divisor, 16, Message::SetSlowMotionDivisor)
+                    .style(NumberInputStyles::Default)
+                    .width(Length::Fixed(60.))
+                    .step(1)
+                    .into(),
+                Container::new(Text::new("Render mode:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                pick_list(RenderMode::ALL, Some(self.canvas.render_mode), Message::SetRenderMode).into(),
+                Container::new(Text::new("Persistence frames:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                number_input(self.canvas.persistence_frames, 60, Message::SetPersistenceFrames)
+                    .style(NumberInputStyles::Default)
+                    .width(Length::Fixed(60.))
+                    .step(1)
+                    .into(),
+                Container::new(Text::new("Palette:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                pick_list(Palette::PRESETS, Some(self.canvas.palette), Message::SetPalette).into(),
+                Container::new(Text::new("Rotation:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                pick_list(Rotation::ALL, Some(self.canvas.rotation), Message::SetRotation).into(),
+                Container::new(Text::new("Capture scale:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                number_input(self.capture_scale, 32, Message::SetCaptureScale)
+                    .style(NumberInputStyles::Default)
+                    .width(Length::Fixed(60.))
+                    .step(1)
+                    .into(),
+                Container::new(Text::new("Panel:")).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
+                pick_list(Panel::ALL, Some(self.panel), Message::SetPanel).into(),
+                Container::new(Text::new(&self.status)).height(Length::Fill).padding([0, 0, 0, 25]).center_y().into(),
             ]).height(Length::Shrink),
+            self.quirks_view(),
             row([
                 self.canvas.view(),
+                self.panel_view(),
             ]).height(Length::Fill),
         ].into()
     }
 }
 
-pub fn create_ui() {
-    Chip8Emu::run(Settings::default()).expect("Failed to launch application.");
+impl Chip8Emu {
+    /// Real time between game ticks, stretched by the slow-motion divisor.
+    fn tick_interval(&self) -> Duration {
+        Duration::from_secs_f64(self.slow_motion_divisor as f64 / (self.ticks_per_second as f64))
+    }
+
+    /// Emulates one 60 Hz frame: a tick's worth of instructions followed by one timer decrement.
+    /// Fast-forward and frame advance both go through here so timers stay in step with instructions.
+    fn run_frame(&mut self) {
+        // Borrowed field by field so the hooks and status can change while the machine runs
+        let machine: &mut dyn Machine = match (&mut self.vip, &mut self.state) {
+            (Some(vip), _) => vip,
+            (None, Some(state)) => state,
+            (None, None) => return,
+        };
+        let result = machine.run_frame(self.cycles_per_tick, &mut self.hooks);
+        for warning in machine.take_warnings() {
+            eprintln!("Warning: {}", warning);
+            self.status = format!("Warning: {}", warning);
+            if self.warnings.len() == MAX_WARNINGS {
+                self.warnings.pop_front();
+            }
+            self.warnings.push_back(warning.to_string());
+        }
+        // Waiting for a key is left running, since the key can only arrive while it runs
+        if let Some(halt @ (Halt::SelfJump { .. } | Halt::Stopped { .. })) = machine.halted() {
+            self.paused = true;
+            self.status = format!("Program finished: {}", halt);
+        }
+        if let Err(fault) = result {
+            self.paused = true;
+            self.status = match &self.hooks.tracer {
+                Some(tracer) => format!("CPU fault: {} (trace in {})", fault, tracer.path().display()),
+                None => format!("CPU fault: {}", fault),
+            };
+        }
+        let colour_video = machine.colour_video();
+        if let Some(recorder) = &mut self.recorder {
+            let recorded = match &colour_video {
+                Some(frame) => recorder.push_colour_frame(frame),
+                None => recorder.push_frame(machine.video(), machine.display_width()),
+            };
+            if let Err(error) = recorded {
+                self.recorder = None;
+                self.status = format!("Recording stopped: {}", error);
+            }
+        }
+        match colour_video {
+            Some(frame) => self.canvas.push_colour_frame(frame),
+            None => self.canvas.push_frame(machine.video(), machine.display_width()),
+        }
+        #[cfg(feature = "audio")]
+        if let Some(speaker) = &self.speaker {
+            speaker.play(machine.audio());
+        }
+    }
+
+    /// Switches fonts, rewriting them in the running machine's memory straight away.
+    fn set_font(&mut self, font: font::Font) {
+        if let Some(state) = &mut self.state {
+            font.load_into(state);
+        }
+        self.status = format!("Font: {}", font);
+        self.font = font;
+    }
+
+    /// Whichever machine is running the ROM, if one is loaded.
+    fn machine(&self) -> Option<&dyn Machine> {
+        match (&self.vip, &self.state) {
+            (Some(vip), _) => Some(vip),
+            (None, Some(state)) => Some(state),
+            (None, None) => None,
+        }
+    }
+
+    fn machine_mut(&mut self) -> Option<&mut dyn Machine> {
+        match (&mut self.vip, &mut self.state) {
+            (Some(vip), _) => Some(vip),
+            (None, Some(state)) => Some(state),
+            (None, None) => None,
+        }
+    }
+
+    /// Starts `rom` on a fresh machine: the emulated COSMAC VIP if its images were given, otherwise
+    /// the high-level interpreter.
+    fn start_machine(&mut self, rom: &[u8]) -> Result<(), RomError> {
+        if let Some(images) = &self.vip_images {
+            self.vip = Some(Vip::new(images, rom, &self.platform)?);
+            self.state = None;
+        } else {
+            let mut state = init::init_state(rom, self.platform, self.quirks, &self.font)?;
+            state.stack_policy = self.stack_policy;
+            self.state = Some(state);
+            self.vip = None;
+        }
+        Ok(())
+    }
+
+    fn rom_size(&self) -> usize {
+        self.rom.as_ref().map_or(0, |rom| rom.bytes.len())
+    }
+
+    /// Starts the profile, coverage, strict mode checks and warnings afresh for a new run of the ROM.
+    fn reset_instrumentation(&mut self) {
+        self.hooks.strict = self.state.as_ref().filter(|_| self.strict_mode).map(|state| StrictMode::new(state, self.rom_size(), self.strict_pause));
+        self.warnings.clear();
+        if let Some(profiler) = &mut self.hooks.profiler {
+            *profiler = Profiler::default();
+        }
+        if let Some(coverage) = &mut self.hooks.coverage {
+            *coverage = Coverage::new(self.platform.memory_size);
+        }
+    }
+
+    fn panel_view(&self) -> Element<'_, Message> {
+        match (self.panel, &self.hooks.profiler) {
+            (Panel::Profiler, Some(profiler)) => profiler_view(profiler),
+            (Panel::Memory, _) => self.memory_view(),
+            (Panel::Warnings, _) => column![
+                Text::new(format!("{} warnings", self.warnings.len())),
+                scrollable(Column::with_children(self.warnings.iter().map(|warning| Text::new(warning).font(Font::MONOSPACE).size(12).into()))),
+            ]
+            .spacing(5)
+            .padding(5)
+            .width(Length::Fixed(400.))
+            .into(),
+            _ => Space::with_width(Length::Shrink).into(),
+        }
+    }
+
+    fn memory_view(&self) -> Element<'_, Message> {
+        let page_start = self.memory_page as usize * 256;
+        let lines = (page_start..page_start + 256).step_by(16).map(|line_start| {
+            let bytes = (line_start..line_start + 16).map(|address| {
+                let value = self.machine().and_then(|machine| machine.memory().get(address).copied()).unwrap_or_default();
+                let access = self.hooks.coverage.as_ref().map(|coverage| coverage.access(address)).unwrap_or_default();
+                Container::new(Text::new(format!("{:02X}", value)).font(Font::MONOSPACE).size(12))
+                    .padding([0, 2])
+                    .style(move |_theme: &Theme| container::Appearance {
+                        background: access
+                            .is_touched()
+                            .then(|| Color::from_rgba(if access.executed { 1. } else { 0. }, if access.read { 0.8 } else { 0. }, if access.written { 1. } else { 0. }, 0.45).into()),
+                        ..container::Appearance::default()
+                    })
+                    .into()
+            });
+            row([Text::new(format!("{:03X}", line_start)).font(Font::MONOSPACE).size(12).width(Length::Fixed(30.)).into()]
+                .into_iter()
+                .chain(bytes))
+            .into()
+        });
+        column![
+            row![
+                Container::new(Text::new("Page:")).height(Length::Fill).center_y(),
+                number_input(self.memory_page, (self.platform.memory_size / 256 - 1) as u16, Message::SetMemoryPage)
+                    .style(NumberInputStyles::Default)
+                    .step(1),
+            ]
+            .spacing(5)
+            .height(Length::Shrink),
+            Text::new("Executed: red, read: green, written: blue").size(12),
+            Column::with_children(lines),
+        ]
+        .spacing(5)
+        .padding(5)
+        .into()
+    }
+
+    fn quirks_view(&self) -> Element<'_, Message> {
+        let quirks = self.quirks;
+        let font_set = match self.font {
+            font::Font::Builtin(font_set) => Some(font_set),
+            font::Font::Custom { .. } => None,
+        };
+        row([
+            Container::new(Text::new("Quirks:")).padding([0, 0, 0, 10]).into(),
+            checkbox("Shift VX in place", quirks.shift)
+                .on_toggle(move |shift| Message::SetQuirks(Quirks { shift, ..quirks }))
+                .into(),
+            checkbox("Load/store leaves I", quirks.load_store)
+                .on_toggle(move |load_store| Message::SetQuirks(Quirks { load_store, ..quirks }))
+                .into(),
+            checkbox("Result after VF", quirks.vf_order)
+                .on_toggle(move |vf_order| Message::SetQuirks(Quirks { vf_order, ..quirks }))
+                .into(),
+            checkbox("Clip sprites", quirks.clip).on_toggle(move |clip| Message::SetQuirks(Quirks { clip, ..quirks })).into(),
+            checkbox("BNNN uses VX", quirks.jump).on_toggle(move |jump| Message::SetQuirks(Quirks { jump, ..quirks })).into(),
+            Text::new("Stack depth:").into(),
+            pick_list(StackDepth::ALL, Some(quirks.stack_depth), move |stack_depth| Message::SetQuirks(Quirks { stack_depth, ..quirks })).into(),
+            Text::new("On stack overflow:").into(),
+            pick_list(StackPolicy::ALL, Some(self.stack_policy), Message::SetStackPolicy).into(),
+            Text::new("FX0A waits for:").into(),
+            pick_list(KeyWait::ALL, Some(quirks.key_wait), move |key_wait| Message::SetQuirks(Quirks { key_wait, ..quirks })).into(),
+            Text::new("Timing:").into(),
+            pick_list(Timing::ALL, Some(quirks.timing), move |timing| Message::SetQuirks(Quirks { timing, ..quirks })).into(),
+            Text::new("Platform:").into(),
+            pick_list(PlatformName::ALL, Some(self.platform.name), Message::SetPlatform).into(),
+            Text::new("Font:").into(),
+            pick_list(FontSet::ALL, font_set, Message::SetFontSet).placeholder(self.font.to_string()).into(),
+            button("Load font...").on_press(Message::LoadFont).into(),
+            checkbox("Strict mode", self.strict_mode).on_toggle(Message::SetStrictMode).into(),
+            checkbox("Pause on warnings", self.strict_pause)
+                .on_toggle_maybe(self.strict_mode.then_some(Message::SetStrictPause))
+                .into(),
+        ])
+        .spacing(15)
+        .padding([5, 0])
+        .into()
+    }
+
+    /// Reads a ROM from disk and starts it, remembering it in the recent ROMs list.
+    fn load_rom_file(&mut self, rom_path: PathBuf) -> Command<Message> {
+        let rom = match init::read_rom(&rom_path) {
+            Ok(rom) => rom,
+            Err(error) => {
+                show_rom_error(&rom_path, &error);
+                return Command::none();
+            }
+        };
+        let platform_command = self.set_platform(self.machine.platform(&rom_path, &rom));
+        let options_command = self.apply_octo_options(&rom.options.unwrap_or_default());
+        match self.start_machine(&rom.bytes) {
+            Ok(()) => {
+                self.canvas.reset();
+                self.paused = false;
+                self.status = String::new();
+                recent::add_recent_rom(&mut self.recent_roms, rom_path.clone());
+                self.rom = Some(LoadedRom { path: rom_path, bytes: rom.bytes });
+                self.reset_instrumentation();
+            }
+            Err(error) => show_rom_error(&rom_path, &error),
+        }
+        Command::batch([platform_command, options_command])
+    }
+
+    /// Takes the quirks and speed of `platform`, keeping any given on the command line, dropping
+    /// any a previous ROM's Octo options set. A different platform also brings its font and fits
+    /// the window to its display. The machine itself only changes when the ROM is next started.
+    fn set_platform(&mut self, platform: Platform) -> Command<Message> {
+        self.quirks = platform.quirks;
+        self.machine.apply_quirks(&mut self.quirks);
+        self.cycles_per_tick = match self.ips {
+            Some(ips) => cli::cycles_per_tick(ips, self.ticks_per_second),
+            None => platform.cycles_per_tick,
+        };
+        if platform == self.platform {
+            return Command::none();
+        }
+        self.platform = platform;
+        self.memory_page = self.memory_page.min((platform.memory_size / 256 - 1) as u16);
+        match self.machine.font(&platform) {
+            Ok(font) => self.font = font,
+            Err(error) => self.status = error.to_string(),
+        }
+        self.resize_window()
+    }
+
+    /// Rotates the display and reshapes the window to match, keeping the `--scale` pixel size.
+    fn set_rotation(&mut self, rotation: Rotation) -> Command<Message> {
+        if rotation == self.canvas.rotation {
+            return Command::none();
+        }
+        self.canvas.rotation = rotation;
+        self.canvas.reset_persistence();
+        self.resize_window()
+    }
+
+    /// Sizes the window to show the rotated display at the `--scale` pixel size.
+    fn resize_window(&self) -> Command<Message> {
+        window::resize(window::Id::MAIN, window_size(&self.platform, self.canvas.rotation, self.window_scale))
+    }
+
+    /// Takes the speed, quirks, colours and rotation from options that came with a ROM, going back
+    /// to the chosen colours and rotation where they have none.
+    fn apply_octo_options(&mut self, options: &OctoOptions) -> Command<Message> {
+        if let Some(cycles_per_tick) = options.cycles_per_tick() {
+            self.cycles_per_tick = cycles_per_tick;
+        }
+        options.apply_quirks(&mut self.quirks);
+        self.canvas.palette = options.palette().unwrap_or(self.palette);
+        self.canvas.reset_persistence();
+        self.set_rotation(options.rotation().unwrap_or(self.rotation))
+    }
+
+    /// The current settings in Octo's terms, for exporting a cartridge.
+    fn octo_options(&self) -> OctoOptions {
+        let mut options = OctoOptions {
+            tickrate: Some(self.cycles_per_tick as u32),
+            ..OctoOptions::default()
+        };
+        options.set_quirks(self.quirks);
+        options.set_palette(self.canvas.palette);
+        options.screen_rotation = Some(self.canvas.rotation.degrees());
+        options
+    }
+
+    fn export_cartridge(&mut self) {
+        let (Some(rom), Some(machine)) = (&self.rom, self.machine()) else {
+            return;
+        };
+        let file_name = format!("{}.gif", rom.path.file_stem().unwrap_or_default().to_string_lossy());
+        let Some(cartridge_path) = FileDialog::new().add_filter("Octo cartridge", &["gif"]).set_file_name(file_name).save_file() else {
+            return;
+        };
+        let saved = cartridge::encode(&rom.bytes, &self.octo_options(), machine.video(), machine.display_width(), self.canvas.palette)
+            .and_then(|cartridge| fs::write(&cartridge_path, cartridge).map_err(CaptureError::from));
+        self.status = match saved {
+            Ok(()) => format!("Saved {}", cartridge_path.display()),
+            Err(error) => error.to_string(),
+        };
+    }
 }
 
-#[derive(Debug, Default)]
-struct Chip8EmuCanvas {
-    canvas_cache: canvas::Cache,
-    video: [u64; 32],
+/// Every executed address with its disassembly and count, shaded from clear to red by how hot it is.
+fn profiler_view(profiler: &Profiler) -> Element<'_, Message> {
+    let hottest = profiler.hottest().max(1) as f32;
+    let lines = profiler.executed_addresses().map(|(address, stats)| {
+        let heat = stats.executions as f32 / hottest;
+        Container::new(
+            Text::new(format!("{:03X}  {:<18}{:>10}", address, disasm::disassemble(stats.opcode), stats.executions))
+                .font(Font::MONOSPACE)
+                .size(12),
+        )
+        .width(Length::Fill)
+        .style(move |_theme: &Theme| container::Appearance {
+            background: Some(Color::from_rgba(1., 0.2, 0., 0.8 * heat).into()),
+            ..container::Appearance::default()
+        })
+        .into()
+    });
+    column![Text::new(format!("{} instructions executed", profiler.instructions())), scrollable(Column::with_children(lines)),]
+        .spacing(5)
+        .padding(5)
+        .width(Length::Fixed(300.))
+        .into()
 }
 
-#[derive(Debug, Default)]
-struct Chip8EmuCanvasState {}
+fn show_rom_error(rom_path: &Path, error: &RomError) {
+    MessageDialog::new()
+        .set_level(MessageLevel::Error)
+        .set_title("Could not load ROM")
+        .set_description(format!("{}\n\n{}", rom_path.display(), error))
+        .set_buttons(MessageButtons::Ok)
+        .show();
+}
 
-impl Chip8EmuCanvas {
-    pub fn view(&self) -> Element<Message> {
-        Canvas::new(self).width(Length::Fill).height(Length::Fill).into()
+/// Maps Ctrl/Cmd shortcuts to ROM commands: O to load, R to reset, Shift+R to reload, W to close
+/// and 1-8 to open a recent ROM.
+fn rom_shortcut(key: &keyboard::Key, modifiers: keyboard::Modifiers) -> Option<Message> {
+    let keyboard::key::Key::Character(character) = key.as_ref() else {
+        return None;
+    };
+    match character.to_lowercase().as_str() {
+        "o" => Some(Message::LoadRom),
+        "r" if modifiers.shift() => Some(Message::ReloadRom),
+        "r" => Some(Message::ResetRom),
+        "w" => Some(Message::CloseRom),
+        digit => digit.parse::<usize>().ok().filter(|index| (1..=8).contains(index)).map(Message::OpenRecentRomAt),
     }
 }
 
-impl canvas::Program<Message> for Chip8EmuCanvas {
-    type State = Chip8EmuCanvasState;
+/// Builds a timestamped file name in the working directory for a screenshot or recording.
+/// Window size that shows `platform`'s display rotated, with each pixel `scale` points across,
+/// above the toolbar.
+fn window_size(platform: &Platform, rotation: Rotation, scale: f32) -> Size {
+    let (width, height) = platform.window_size();
+    let (width, height) = rotation.rotated_size(width, height);
+    Size::new(width as f32 * scale, height as f32 * scale + TOOLBAR_HEIGHT)
+}
 
-    fn draw(&self, _state: &Chip8EmuCanvasState, renderer: &Renderer, _theme: &Theme, bounds: Rectangle, _cursor: mouse::Cursor) -> Vec<Geometry> {
-        let screen = self.canvas_cache.draw(renderer, bounds.size(), |frame| {
-            let screen_size = frame.size();
-            let point_size = Size {
-                width: screen_size.width / 64.,
-                height: screen_size.height / 32.,
-            };
+fn capture_path(prefix: &str, extension: &str) -> PathBuf {
+    let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).map(|elapsed| elapsed.as_millis()).unwrap_or_default();
+    PathBuf::from(format!("{}-{}.{}", prefix, timestamp, extension))
+}
 
-            // Draw a black background
-            let background = iced::widget::canvas::Path::rectangle(Point::ORIGIN, screen_size);
-            frame.fill(&background, Color::BLACK);
-
-            // Draw each of the white pixels
-            frame.with_save(|frame| {
-                for row in 0..32 {
-                    let video_row = self.video[row];
-                    for col in 0..64 {
-                        let bitmask = 1u64.rotate_right(col + 1);
-                        if video_row & bitmask != 0 {
-                            let point = Point {
-                                x: point_size.width * col as f32,
-                                y: point_size.height * row as f32,
-                            };
-                            frame.fill_rectangle(point, point_size, Color::WHITE);
+pub fn create_ui(args: Args) {
+    let keymap = match &args.keymap {
+        Some(keymap_path) => Keymap::from_file(keymap_path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(2);
+        }),
+        None => Keymap::default(),
+    };
+    let tracer = args.trace.tracer().unwrap_or_else(|error| {
+        eprintln!("Could not create trace file: {}", error);
+        process::exit(2);
+    });
+    let platform = args.machine.platform.unwrap_or_default().platform();
+    let font = args.machine.font(&platform).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(2);
+    });
+    let vip_images = args.machine.vip_images().unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(2);
+    });
+    let mut quirks = platform.quirks;
+    args.machine.apply_quirks(&mut quirks);
+    let default_flags = Chip8EmuFlags::default();
+    let cycles_per_tick = match args.ips {
+        Some(ips) => cli::cycles_per_tick(ips, default_flags.ticks_per_second),
+        None => platform.cycles_per_tick,
+    };
+    let scale = args.scale.max(1) as f32;
+    let flags = Chip8EmuFlags {
+        cycles_per_tick,
+        window_scale: scale,
+        rom_path: args.rom,
+        paused: args.paused,
+        palette: args.palette,
+        fullscreen: args.fullscreen,
+        keymap,
+        tracer,
+        platform,
+        ips: args.ips,
+        quirks,
+        font,
+        stack_policy: args.machine.stack_policy,
+        machine: args.machine,
+        vip_images,
+        strict_mode: args.strict.strict,
+        strict_pause: args.strict.strict_pause,
+        ..default_flags
+    };
+    let settings = Settings {
+        window: window::Settings {
+            size: window_size(&platform, Rotation::None, scale),
+            ..window::Settings::default()
+        },
+        ..Settings::with_flags(flags)
+    };
+    Chip8Emu::run(settings).expect("Failed to launch application.");
+}
+
+#[derive(Debug)]
+struct Chip8EmuCanvas {
+    image: image::Handle,
+    video: Vec<u128>,
+    /// Pixels across each `video` row.
+    width: usize,
+    render_mode: RenderMode,
+    persistence_frames: u8,
+    palette: Palette,
+    rotation: Rotation,
+    history: VecDeque<Vec<u128>>,
+    /// Brightness of each pixel, row by row.
+    intensity: Vec<f32>,
+    /// Shown instead of `video` while the machine's display is in colour.
+    colour: Option<ColourFrame>,
+}
+
+impl Default for Chip8EmuCanvas {
+    fn default() -> Self {
+        let platform = Platform::default();
+        let intensity = vec![0.; platform.display_width * platform.display_height];
+        Self {
+            image: framebuffer_image(&intensity, platform.display_width, Palette::default(), Rotation::default()),
+            video: vec![0; platform.display_height],
+            width: platform.display_width,
+            render_mode: RenderMode::default(),
+            persistence_frames: 4,
+            palette: Palette::default(),
+            rotation: Rotation::default(),
+            history: VecDeque::new(),
+            intensity,
+            colour: None,
+        }
+    }
+}
+
+impl Chip8EmuCanvas {
+    /// Shows the whole framebuffer as one image, scaled up with nearest-neighbour filtering so pixels stay sharp.
+    pub fn view(&self) -> Element<'_, Message> {
+        Image::new(self.image.clone())
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .content_fit(ContentFit::Fill)
+            .filter_method(FilterMethod::Nearest)
+            .into()
+    }
+    /// Feeds one emulated frame to the canvas, updating the per-pixel brightness for the current render mode.
+    /// A frame of a different size starts the persistence afresh.
+    fn push_frame(&mut self, video: &[u128], width: usize) {
+        let was_colour = self.colour.take().is_some();
+        if video.len() != self.video.len() || width != self.width || was_colour {
+            self.history.clear();
+            self.intensity = vec![0.; width * video.len()];
+        }
+        let previous_intensity = self.intensity.clone();
+        self.video = video.to_vec();
+        self.width = width;
+        let lit = |row: usize, col: usize| video[row] & pixel_bitmask(col) != 0;
+        match self.render_mode {
+            RenderMode::Direct => {
+                for (pixel, intensity) in self.intensity.iter_mut().enumerate() {
+                    *intensity = if lit(pixel / width, pixel % width) { 1. } else { 0. };
+                }
+            }
+            RenderMode::Fade => {
+                let decay = 1. / self.persistence_frames as f32;
+                for (pixel, intensity) in self.intensity.iter_mut().enumerate() {
+                    *intensity = if lit(pixel / width, pixel % width) { 1. } else { (*intensity - decay).max(0.) };
+                }
+            }
+            RenderMode::Blend => {
+                self.history.push_back(video.to_vec());
+                while self.history.len() > self.persistence_frames as usize {
+                    self.history.pop_front();
+                }
+                let frame_weight = 1. / self.history.len() as f32;
+                self.intensity.fill(0.);
+                for frame in &self.history {
+                    for (pixel, intensity) in self.intensity.iter_mut().enumerate() {
+                        if frame[pixel / width] & pixel_bitmask(pixel % width) != 0 {
+                            *intensity += frame_weight;
                         }
                     }
                 }
+            }
+        }
+        if self.intensity != previous_intensity || was_colour {
+            self.image = framebuffer_image(&self.intensity, width, self.palette, self.rotation);
+        }
+    }
+
+    /// Shows a colour frame as it is. The render modes only apply to the monochrome display.
+    fn push_colour_frame(&mut self, frame: ColourFrame) {
+        if self.colour.as_ref() != Some(&frame) {
+            self.image = colour_image(&frame, self.rotation);
+            self.colour = Some(frame);
+        }
+    }
+
+    /// Forgets any persisted frames so the next frame is drawn as-is.
+    fn reset_persistence(&mut self) {
+        if let Some(frame) = &self.colour {
+            self.image = colour_image(frame, self.rotation);
+            return;
+        }
+        self.history.clear();
+        self.intensity.fill(0.);
+        self.push_frame(&self.video.clone(), self.width);
+        self.image = framebuffer_image(&self.intensity, self.width, self.palette, self.rotation);
+    }
+
+    fn reset(&mut self) {
+        self.colour = None;
+        self.video.fill(0);
+        self.reset_persistence();
+    }
+}
+
+/// Converts per-pixel brightness, `width` pixels across, into a rotated RGBA image in the given palette.
+pub fn framebuffer_image(intensity: &[f32], width: usize, palette: Palette, rotation: Rotation) -> image::Handle {
+    let colours: Vec<[u8; 3]> = intensity.iter().map(|intensity| palette.blend(*intensity)).collect();
+    let (rotated_width, rotated_height) = rotation.rotated_size(width, intensity.len() / width);
+    let pixels: Vec<u8> = rotation
+        .rotate(&colours, width, intensity.len() / width)
+        .into_iter()
+        .flat_map(|[red, green, blue]| [red, green, blue, 255])
+        .collect();
+    image::Handle::from_pixels(rotated_width as u32, rotated_height as u32, pixels)
+}
+
+/// Converts a colour frame into a rotated RGBA image.
+pub fn colour_image(frame: &ColourFrame, rotation: Rotation) -> image::Handle {
+    let (width, height) = rotation.rotated_size(frame.width, frame.height);
+    let pixels: Vec<u8> = rotation
+        .rotate(&frame.pixels, frame.width, frame.height)
+        .into_iter()
+        .flat_map(|[red, green, blue]| [red, green, blue, 255])
+        .collect();
+    image::Handle::from_pixels(width as u32, height as u32, pixels)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A canvas in `render_mode` with four frames of persistence, and a frame with only the top
+    /// left pixel lit.
+    fn canvas(render_mode: RenderMode) -> (Chip8EmuCanvas, Vec<u128>) {
+        let canvas = Chip8EmuCanvas {
+            render_mode,
+            persistence_frames: 4,
+            ..Chip8EmuCanvas::default()
+        };
+        let mut lit = vec![0; 32];
+        lit[0] = pixel_bitmask(0);
+        (canvas, lit)
+    }
+
+    /// The top left pixel's brightness after each frame in turn.
+    fn brightness(canvas: &mut Chip8EmuCanvas, frames: &[&[u128]]) -> Vec<f32> {
+        frames
+            .iter()
+            .map(|video| {
+                canvas.push_frame(video, 64);
+                canvas.intensity[0]
             })
-        });
-        vec![screen]
+            .collect()
+    }
+
+    #[test]
+    fn direct_mode_shows_only_the_current_frame() {
+        let (mut canvas, lit) = canvas(RenderMode::Direct);
+        let dark = vec![0; 32];
+        assert_eq!(brightness(&mut canvas, &[&lit, &dark, &lit]), [1., 0., 1.]);
+        assert!(canvas.intensity[1..].iter().all(|intensity| *intensity == 0.));
+    }
+
+    #[test]
+    fn fade_mode_decays_erased_pixels_over_the_persistence_frames() {
+        let (mut canvas, lit) = canvas(RenderMode::Fade);
+        let dark = vec![0; 32];
+        assert_eq!(brightness(&mut canvas, &[&lit, &dark, &dark, &dark, &dark, &dark]), [1., 0.75, 0.5, 0.25, 0., 0.]);
+        // Lighting the pixel again shows it at full brightness straight away
+        assert_eq!(brightness(&mut canvas, &[&dark, &lit]), [0., 1.]);
+    }
+
+    #[test]
+    fn blend_mode_averages_the_persistence_frames() {
+        let (mut canvas, lit) = canvas(RenderMode::Blend);
+        let dark = vec![0; 32];
+        assert_eq!(brightness(&mut canvas, &[&lit, &dark, &lit, &dark, &dark, &dark]), [1., 0.5, 2. / 3., 0.5, 0.25, 0.25]);
+        assert_eq!(brightness(&mut canvas, &[&dark]), [0.]);
+    }
+
+    #[test]
+    fn a_frame_of_another_size_starts_persistence_afresh() {
+        let (mut canvas, lit) = canvas(RenderMode::Fade);
+        brightness(&mut canvas, &[&lit]);
+        canvas.push_frame(&[0; 64], 128);
+        assert_eq!(canvas.intensity, vec![0.; 128 * 64]);
+        canvas.reset_persistence();
+        assert_eq!(canvas.history.len(), 0);
+    }
+
+    #[test]
+    fn the_window_follows_the_rotation() {
+        let platform = PlatformName::Chip8.platform();
+        assert_eq!(window_size(&platform, Rotation::None, 10.), Size::new(640., 320. + TOOLBAR_HEIGHT));
+        assert_eq!(window_size(&platform, Rotation::Clockwise90, 10.), Size::new(320., 640. + TOOLBAR_HEIGHT));
+        assert_eq!(window_size(&platform, Rotation::Clockwise180, 10.), Size::new(640., 320. + TOOLBAR_HEIGHT));
+        assert_eq!(window_size(&platform, Rotation::Clockwise270, 10.), Size::new(320., 640. + TOOLBAR_HEIGHT));
+    }
+
+    #[test]
+    fn framebuffer_image_rotates_the_display() {
+        let handle = framebuffer_image(&[1., 0., 0., 0., 0., 0.], 3, Palette::Classic, Rotation::Clockwise90);
+        let iced::advanced::image::Data::Rgba { width, height, pixels } = handle.data() else {
+            panic!("not an RGBA image");
+        };
+        assert_eq!((*width, *height), (2, 3));
+        // The top left pixel ends up top right
+        assert_eq!(pixels[4..8], Palette::Classic.blend(1.).into_iter().chain([255]).collect::<Vec<u8>>()[..]);
+        assert_eq!(pixels[..4], Palette::Classic.blend(0.).into_iter().chain([255]).collect::<Vec<u8>>()[..]);
+    }
+
+    #[test]
+    fn framebuffer_image_blends_each_pixel_into_the_palette() {
+        let (palette, rotation) = (Palette::Classic, Rotation::None);
+        let handle = framebuffer_image(&[0., 0.5, 1., 0.], 2, palette, rotation);
+        let iced::advanced::image::Data::Rgba { width, height, pixels } = handle.data() else {
+            panic!("not an RGBA image");
+        };
+        assert_eq!((*width, *height), (2, 2));
+        let expected: Vec<u8> = [0., 0.5, 1., 0.]
+            .iter()
+            .flat_map(|intensity| {
+                let [red, green, blue] = palette.blend(*intensity);
+                [red, green, blue, 255]
+            })
+            .collect();
+        assert_eq!(pixels.to_vec(), expected);
     }
 }
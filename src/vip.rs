@@ -0,0 +1,654 @@
+//! COSMAC VIP system emulation: a CDP1802 CPU, the CDP1861 video chip and the hex keypad, running
+//! the RCA monitor ROM and a CHIP-8 interpreter image supplied by the user.
+//!
+//! Memory is 4 KiB of RAM, mirrored up to 0x7FFF, and the 512-byte monitor ROM, mirrored from
+//! 0x8000. After reset the ROM also answers at 0x0000 until the first access above 0x7FFF, so the
+//! 1802 starts in the monitor. Unless key C is held, the monitor then runs the program in RAM at
+//! 0x0000: the interpreter, which runs the CHIP-8 program loaded at 0x200.
+//!
+//! The 1861 draws 262 lines of 14 machine cycles each frame. Lines 80 to 207 each show 8 bytes
+//! fetched by DMA from the address in R0, which the interpreter's interrupt routine points at the
+//! display buffer. The interrupt comes two lines before the display starts, and EF1 is raised for
+//! the four lines before the display starts and before it ends. `INP 1` turns the display on and
+//! `OUT 1` turns it off. `OUT 2` selects a key, and EF3 shows whether that key is held.
+
+use crate::cpu::CpuFault;
+use crate::cpu::Halt;
+use crate::cpu::Hooks;
+use crate::init::RomError;
+use crate::machine::Machine;
+use crate::platform::Platform;
+use crate::state::Keypad;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::path::PathBuf;
+
+static RAM_SIZE: usize = 4096;
+static MONITOR_SIZE: usize = 512;
+/// Where the interpreter expects CHIP-8 programs, and so the most room the interpreter can take.
+static PROGRAM_START: usize = 0x200;
+static CYCLES_PER_LINE: u32 = 14;
+static LINES_PER_FRAME: u32 = 262;
+static INTERRUPT_LINE: u32 = 78;
+static FIRST_DISPLAY_LINE: u32 = 80;
+static DISPLAY_LINES: u32 = 128;
+static DMA_BYTES_PER_LINE: u32 = 8;
+
+/// The monitor ROM and interpreter image the VIP needs besides the CHIP-8 program. Both are RCA's,
+/// so they are read from the user's own copies.
+#[derive(Debug, Clone)]
+pub struct VipImages {
+    monitor: Vec<u8>,
+    interpreter: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub enum VipError {
+    Unreadable { path: PathBuf, error: io::Error },
+    TooLarge { path: PathBuf, size: usize, max_size: usize },
+}
+
+impl fmt::Display for VipError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VipError::Unreadable { path, error } => write!(f, "could not read {}: {}", path.display(), error),
+            VipError::TooLarge { path, size, max_size } => write!(f, "{} is {} bytes but at most {} bytes fit", path.display(), size, max_size),
+        }
+    }
+}
+
+impl VipImages {
+    pub fn from_files(monitor_path: &Path, interpreter_path: &Path) -> Result<Self, VipError> {
+        Ok(Self {
+            monitor: read_image(monitor_path, MONITOR_SIZE)?,
+            interpreter: read_image(interpreter_path, PROGRAM_START)?,
+        })
+    }
+}
+
+fn read_image(path: &Path, max_size: usize) -> Result<Vec<u8>, VipError> {
+    let image = fs::read(path).map_err(|error| VipError::Unreadable { path: path.to_path_buf(), error })?;
+    if image.len() > max_size {
+        return Err(VipError::TooLarge {
+            path: path.to_path_buf(),
+            size: image.len(),
+            max_size,
+        });
+    }
+    Ok(image)
+}
+
+/// CDP1802 registers. `r[p]` is the program counter and `r[x]` the data pointer.
+#[derive(Debug, Clone, Default)]
+struct Cdp1802 {
+    r: [u16; 16],
+    p: usize,
+    x: usize,
+    d: u8,
+    df: bool,
+    /// X and P saved by an interrupt or MARK.
+    t: u8,
+    ie: bool,
+    q: bool,
+    /// Stopped by IDL until the next DMA or interrupt.
+    idle: bool,
+}
+
+/// A COSMAC VIP with a CHIP-8 program loaded, emulated machine cycle by machine cycle.
+#[derive(Debug, Clone)]
+pub struct Vip {
+    cpu: Cdp1802,
+    ram: Vec<u8>,
+    monitor: Vec<u8>,
+    /// The monitor also answers at 0x0000 from reset until the first access above 0x7FFF.
+    monitor_at_zero: bool,
+    keypad: Keypad,
+    /// Key chosen by the last `OUT 2`, which EF3 reports on.
+    selected_key: u8,
+    display_on: bool,
+    /// Machine cycles since the start of the frame.
+    cycle: u32,
+    /// Display line the DMA count belongs to.
+    dma_line: u32,
+    dma_bytes: u32,
+    /// The 128 lines drawn so far this frame.
+    lines: Vec<u64>,
+    video: Vec<u128>,
+}
+
+impl Vip {
+    /// Loads the interpreter and `rom` into RAM and resets the machine into the monitor. The display
+    /// shows the platform's rows, sampled evenly from the 1861's 128 lines. Platforms whose programs
+    /// need more than an unexpanded VIP are refused.
+    pub fn new(images: &VipImages, rom: &[u8], platform: &Platform) -> Result<Self, RomError> {
+        if !platform.name.runs_on_vip() {
+            return Err(RomError::NotForVip(platform.name));
+        }
+        let load_address = platform.load_address as usize;
+        let max_size = RAM_SIZE - load_address;
+        if rom.is_empty() {
+            return Err(RomError::Empty);
+        }
+        if rom.len() > max_size {
+            return Err(RomError::TooLarge { size: rom.len(), max_size });
+        }
+        let mut ram = vec![0; RAM_SIZE];
+        ram[..images.interpreter.len()].copy_from_slice(&images.interpreter);
+        ram[load_address..load_address + rom.len()].copy_from_slice(rom);
+        let mut monitor = images.monitor.clone();
+        monitor.resize(MONITOR_SIZE, 0);
+        Ok(Self {
+            cpu: Cdp1802 { ie: true, ..Cdp1802::default() },
+            ram,
+            monitor,
+            monitor_at_zero: true,
+            keypad: Keypad::default(),
+            selected_key: 0,
+            display_on: false,
+            cycle: 0,
+            dma_line: 0,
+            dma_bytes: 0,
+            lines: vec![0; DISPLAY_LINES as usize],
+            video: vec![0; platform.display_height],
+        })
+    }
+
+    fn read(&mut self, address: u16) -> u8 {
+        if address & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+            self.monitor[address as usize % MONITOR_SIZE]
+        } else if self.monitor_at_zero {
+            self.monitor[address as usize % MONITOR_SIZE]
+        } else {
+            self.ram[address as usize % RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+        } else {
+            self.ram[address as usize % RAM_SIZE] = value;
+        }
+    }
+
+    /// Reads the byte at R(P) and steps past it.
+    fn fetch(&mut self) -> u8 {
+        let address = self.cpu.r[self.cpu.p];
+        self.cpu.r[self.cpu.p] = address.wrapping_add(1);
+        self.read(address)
+    }
+
+    fn line(&self) -> u32 {
+        self.cycle / CYCLES_PER_LINE
+    }
+
+    fn dma_requested(&self) -> bool {
+        let line = self.line();
+        self.display_on && (FIRST_DISPLAY_LINE..FIRST_DISPLAY_LINE + DISPLAY_LINES).contains(&line) && (self.dma_line != line || self.dma_bytes < DMA_BYTES_PER_LINE)
+    }
+
+    fn interrupt_requested(&self) -> bool {
+        self.display_on && self.cpu.ie && (INTERRUPT_LINE..FIRST_DISPLAY_LINE).contains(&self.line())
+    }
+
+    /// The 1861's frame flag, raised for the four lines before the display starts and ends.
+    fn frame_flag(&self) -> bool {
+        let line = self.line();
+        (FIRST_DISPLAY_LINE - 4..FIRST_DISPLAY_LINE).contains(&line) || (FIRST_DISPLAY_LINE + DISPLAY_LINES - 4..FIRST_DISPLAY_LINE + DISPLAY_LINES).contains(&line)
+    }
+
+    /// One DMA out cycle: the 1861 takes the byte at R0 for the current line.
+    fn dma_out(&mut self) {
+        let line = self.line();
+        if self.dma_line != line {
+            self.dma_line = line;
+            self.dma_bytes = 0;
+        }
+        let address = self.cpu.r[0];
+        self.cpu.r[0] = address.wrapping_add(1);
+        let byte = self.read(address) as u64;
+        let shift = 56 - 8 * self.dma_bytes;
+        let row = &mut self.lines[(line - FIRST_DISPLAY_LINE) as usize];
+        *row = (*row & !(0xFF << shift)) | (byte << shift);
+        self.dma_bytes += 1;
+        self.cpu.idle = false;
+    }
+
+    fn interrupt(&mut self) {
+        self.cpu.t = ((self.cpu.x as u8) << 4) | self.cpu.p as u8;
+        self.cpu.p = 1;
+        self.cpu.x = 2;
+        self.cpu.ie = false;
+        self.cpu.idle = false;
+    }
+
+    /// Runs one instruction and returns how many machine cycles it took.
+    fn execute(&mut self) -> u32 {
+        let opcode = self.fetch();
+        let n = (opcode & 0x0F) as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.cpu.idle = true,
+            0x0 => self.cpu.d = self.read(self.cpu.r[n]),
+            0x1 => self.cpu.r[n] = self.cpu.r[n].wrapping_add(1),
+            0x2 => self.cpu.r[n] = self.cpu.r[n].wrapping_sub(1),
+            0x3 => {
+                let target = self.fetch();
+                if self.condition(n & 0x7) != (n & 0x8 != 0) {
+                    let page = self.cpu.r[self.cpu.p].wrapping_sub(1) & 0xFF00;
+                    self.cpu.r[self.cpu.p] = page | target as u16;
+                }
+            }
+            0x4 => {
+                self.cpu.d = self.read(self.cpu.r[n]);
+                self.cpu.r[n] = self.cpu.r[n].wrapping_add(1);
+            }
+            0x5 => self.write(self.cpu.r[n], self.cpu.d),
+            0x6 => self.input_output(n),
+            0x7 => self.op_7(n),
+            0x8 => self.cpu.d = self.cpu.r[n] as u8,
+            0x9 => self.cpu.d = (self.cpu.r[n] >> 8) as u8,
+            0xA => self.cpu.r[n] = (self.cpu.r[n] & 0xFF00) | self.cpu.d as u16,
+            0xB => self.cpu.r[n] = (self.cpu.r[n] & 0x00FF) | (self.cpu.d as u16) << 8,
+            0xC => {
+                self.long_branch_or_skip(n);
+                return 3;
+            }
+            0xD => self.cpu.p = n,
+            0xE => self.cpu.x = n,
+            _ => self.op_f(n),
+        }
+        2
+    }
+
+    /// Branch conditions 0 to 7: always, Q, D zero, DF, then EF1 to EF4.
+    fn condition(&self, condition: usize) -> bool {
+        match condition {
+            0 => true,
+            1 => self.cpu.q,
+            2 => self.cpu.d == 0,
+            3 => self.cpu.df,
+            4 => self.frame_flag(),
+            6 => self.keypad.is_held(self.selected_key),
+            _ => false,
+        }
+    }
+
+    /// `C0` to `CF`: long branches on the usual conditions, and long skips that step over two bytes.
+    fn long_branch_or_skip(&mut self, n: usize) {
+        let pc = self.cpu.r[self.cpu.p];
+        if n & 0x4 == 0 {
+            if self.condition(n & 0x3) != (n & 0x8 != 0) {
+                let high = self.read(pc);
+                let low = self.read(pc.wrapping_add(1));
+                self.cpu.r[self.cpu.p] = u16::from_be_bytes([high, low]);
+            } else {
+                self.cpu.r[self.cpu.p] = pc.wrapping_add(2);
+            }
+        } else {
+            let skip = match n & 0x3 {
+                0 => n == 0xC && self.cpu.ie,
+                condition => self.condition(condition) == (n & 0x8 != 0),
+            };
+            if skip {
+                self.cpu.r[self.cpu.p] = pc.wrapping_add(2);
+            }
+        }
+    }
+
+    /// `60` to `6F`: IRX, and output from or input to memory at R(X) on the I/O lines.
+    fn input_output(&mut self, n: usize) {
+        let address = self.cpu.r[self.cpu.x];
+        match n {
+            0 => self.cpu.r[self.cpu.x] = address.wrapping_add(1),
+            1..=7 => {
+                let byte = self.read(address);
+                self.cpu.r[self.cpu.x] = address.wrapping_add(1);
+                match n {
+                    1 => self.display_on = false,
+                    2 => self.selected_key = byte & 0x0F,
+                    _ => {}
+                }
+            }
+            8 => {}
+            _ => {
+                if n == 9 {
+                    self.display_on = true;
+                }
+                // Nothing drives the data bus on input, and its pull-ups read as all ones
+                self.write(address, 0xFF);
+                self.cpu.d = 0xFF;
+            }
+        }
+    }
+
+    fn op_7(&mut self, n: usize) {
+        match n {
+            0x0 | 0x1 => {
+                let address = self.cpu.r[self.cpu.x];
+                let byte = self.read(address);
+                self.cpu.r[self.cpu.x] = address.wrapping_add(1);
+                self.cpu.x = (byte >> 4) as usize;
+                self.cpu.p = (byte & 0x0F) as usize;
+                self.cpu.ie = n == 0x0;
+            }
+            0x2 => {
+                let address = self.cpu.r[self.cpu.x];
+                self.cpu.d = self.read(address);
+                self.cpu.r[self.cpu.x] = address.wrapping_add(1);
+            }
+            0x3 => {
+                let address = self.cpu.r[self.cpu.x];
+                self.write(address, self.cpu.d);
+                self.cpu.r[self.cpu.x] = address.wrapping_sub(1);
+            }
+            0x4 | 0xC => {
+                let operand = self.operand(n);
+                self.add(operand, self.cpu.df);
+            }
+            0x5 | 0xD => {
+                let operand = self.operand(n);
+                self.subtract(operand, self.cpu.d, !self.cpu.df);
+            }
+            0x7 | 0xF => {
+                let operand = self.operand(n);
+                self.subtract(self.cpu.d, operand, !self.cpu.df);
+            }
+            0x6 => {
+                let carry = self.cpu.d & 0x01 != 0;
+                self.cpu.d = (self.cpu.d >> 1) | (self.cpu.df as u8) << 7;
+                self.cpu.df = carry;
+            }
+            0xE => {
+                let carry = self.cpu.d & 0x80 != 0;
+                self.cpu.d = (self.cpu.d << 1) | self.cpu.df as u8;
+                self.cpu.df = carry;
+            }
+            0x8 => self.write(self.cpu.r[self.cpu.x], self.cpu.t),
+            0x9 => {
+                self.cpu.t = ((self.cpu.x as u8) << 4) | self.cpu.p as u8;
+                self.write(self.cpu.r[2], self.cpu.t);
+                self.cpu.x = self.cpu.p;
+                self.cpu.r[2] = self.cpu.r[2].wrapping_sub(1);
+            }
+            0xA => self.cpu.q = false,
+            _ => self.cpu.q = true,
+        }
+    }
+
+    fn op_f(&mut self, n: usize) {
+        match n {
+            0x6 => {
+                self.cpu.df = self.cpu.d & 0x01 != 0;
+                self.cpu.d >>= 1;
+            }
+            0xE => {
+                self.cpu.df = self.cpu.d & 0x80 != 0;
+                self.cpu.d <<= 1;
+            }
+            _ => {
+                let operand = self.operand(n);
+                match n & 0x7 {
+                    0x0 => self.cpu.d = operand,
+                    0x1 => self.cpu.d |= operand,
+                    0x2 => self.cpu.d &= operand,
+                    0x3 => self.cpu.d ^= operand,
+                    0x4 => self.add(operand, false),
+                    0x5 => self.subtract(operand, self.cpu.d, false),
+                    _ => self.subtract(self.cpu.d, operand, false),
+                }
+            }
+        }
+    }
+
+    /// The memory operand of an ALU instruction: M(R(X)), or the immediate byte when bit 3 of N is set.
+    fn operand(&mut self, n: usize) -> u8 {
+        if n & 0x8 != 0 {
+            self.fetch()
+        } else {
+            self.read(self.cpu.r[self.cpu.x])
+        }
+    }
+
+    fn add(&mut self, operand: u8, carry: bool) {
+        let sum = self.cpu.d as u16 + operand as u16 + carry as u16;
+        self.cpu.d = sum as u8;
+        self.cpu.df = sum > 0xFF;
+    }
+
+    /// Sets D to `minuend - subtrahend`, with DF set when nothing was borrowed.
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, borrow: bool) {
+        let difference = minuend as i16 - subtrahend as i16 - borrow as i16;
+        self.cpu.d = difference as u8;
+        self.cpu.df = difference >= 0;
+    }
+
+    /// Shows the finished frame, sampling the 128 lines down to the display's rows.
+    fn end_frame(&mut self) {
+        let rows = self.video.len();
+        for (row, video_row) in self.video.iter_mut().enumerate() {
+            *video_row = if self.display_on { (self.lines[row * DISPLAY_LINES as usize / rows] as u128) << 64 } else { 0 };
+        }
+        self.keypad.end_frame();
+    }
+}
+
+impl Machine for Vip {
+    fn run_frame(&mut self, _cycles_per_tick: u16, _hooks: &mut Hooks) -> Result<(), CpuFault> {
+        let frame_cycles = CYCLES_PER_LINE * LINES_PER_FRAME;
+        while self.cycle < frame_cycles {
+            // DMA and interrupts are taken between instructions, DMA first
+            self.cycle += if self.dma_requested() {
+                self.dma_out();
+                1
+            } else if self.interrupt_requested() {
+                self.interrupt();
+                1
+            } else if self.cpu.idle {
+                1
+            } else {
+                self.execute()
+            };
+        }
+        self.cycle -= frame_cycles;
+        self.end_frame();
+        Ok(())
+    }
+
+    fn keypad(&mut self) -> &mut Keypad {
+        &mut self.keypad
+    }
+
+    fn video(&self) -> &[u128] {
+        &self.video
+    }
+
+    fn memory(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn halted(&self) -> Option<Halt> {
+        None
+    }
+
+    fn take_warnings(&mut self) -> Vec<CpuFault> {
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::PlatformName;
+
+    /// A VIP running `program` from RAM at 0x0000, past the monitor.
+    fn running(program: &[u8]) -> Vip {
+        let images = VipImages {
+            monitor: Vec::new(),
+            interpreter: program.to_vec(),
+        };
+        let mut vip = Vip::new(&images, &[0], &Platform::default()).unwrap();
+        vip.monitor_at_zero = false;
+        vip
+    }
+
+    /// D and DF after running the ALU instruction `opcode` on D and M(R(X)).
+    fn alu(opcode: u8, d: u8, df: bool, memory: u8) -> (u8, bool) {
+        let mut vip = running(&[opcode]);
+        vip.cpu.x = 2;
+        vip.cpu.r[2] = 0x100;
+        vip.ram[0x100] = memory;
+        vip.cpu.d = d;
+        vip.cpu.df = df;
+        vip.execute();
+        (vip.cpu.d, vip.cpu.df)
+    }
+
+    #[test]
+    fn short_branches_and_skips() {
+        let mut vip = running(&[0x38, 0xAA, 0x32, 0x10, 0x3A, 0x20]);
+        assert_eq!(vip.execute(), 2);
+        assert_eq!(vip.cpu.r[0], 0x02);
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x10);
+        vip.cpu.r[0] = 0x04;
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x06);
+    }
+
+    #[test]
+    fn short_branch_stays_on_the_page_of_its_operand() {
+        let mut vip = running(&[]);
+        vip.ram[0x1FF] = 0x30;
+        vip.ram[0x200] = 0x20;
+        vip.cpu.r[0] = 0x1FF;
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x220);
+    }
+
+    #[test]
+    fn long_branches_and_skips() {
+        let mut vip = running(&[0xC4, 0xC8, 0xAA, 0xBB, 0xCC, 0xAA, 0xBB, 0xCC, 0xC0, 0x01, 0x23]);
+        assert_eq!(vip.execute(), 3);
+        assert_eq!(vip.cpu.r[0], 0x01);
+        assert_eq!(vip.execute(), 3);
+        assert_eq!(vip.cpu.r[0], 0x04);
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x07);
+        vip.cpu.ie = false;
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x08);
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x0123);
+    }
+
+    #[test]
+    fn subtraction_sets_df_when_nothing_is_borrowed() {
+        // SD: M - D
+        assert_eq!(alu(0xF5, 3, false, 5), (2, true));
+        assert_eq!(alu(0xF5, 7, false, 5), (0xFE, false));
+        // SM: D - M
+        assert_eq!(alu(0xF7, 7, false, 5), (2, true));
+        assert_eq!(alu(0xF7, 3, true, 5), (0xFE, false));
+        // SDB: M - D - borrow
+        assert_eq!(alu(0x75, 3, true, 5), (2, true));
+        assert_eq!(alu(0x75, 3, false, 5), (1, true));
+        assert_eq!(alu(0x75, 5, false, 5), (0xFF, false));
+        // SMB: D - M - borrow
+        assert_eq!(alu(0x77, 5, true, 5), (0, true));
+        assert_eq!(alu(0x77, 5, false, 5), (0xFF, false));
+    }
+
+    #[test]
+    fn mark_and_return_save_and_restore_x_and_p() {
+        // MARK, SEX 2, INC 2, RET
+        let mut vip = running(&[0x79, 0xE2, 0x12, 0x70]);
+        vip.cpu.x = 3;
+        vip.cpu.r[2] = 0x100;
+        vip.cpu.ie = false;
+        vip.execute();
+        assert_eq!(vip.cpu.t, 0x30);
+        assert_eq!(vip.ram[0x100], 0x30);
+        assert_eq!(vip.cpu.x, 0);
+        assert_eq!(vip.cpu.r[2], 0xFF);
+        vip.execute();
+        vip.execute();
+        vip.execute();
+        assert_eq!((vip.cpu.x, vip.cpu.p), (3, 0));
+        assert_eq!(vip.cpu.r[2], 0x101);
+        assert!(vip.cpu.ie);
+    }
+
+    #[test]
+    fn interrupt_saves_x_and_p_in_t() {
+        let mut vip = running(&[]);
+        vip.display_on = true;
+        vip.cpu.x = 3;
+        vip.cpu.p = 5;
+        vip.cycle = INTERRUPT_LINE * CYCLES_PER_LINE - 1;
+        assert!(!vip.interrupt_requested());
+        vip.cycle += 1;
+        assert!(vip.interrupt_requested());
+        vip.interrupt();
+        assert_eq!(vip.cpu.t, 0x35);
+        assert_eq!((vip.cpu.x, vip.cpu.p), (2, 1));
+        assert!(!vip.cpu.ie);
+        assert!(!vip.interrupt_requested());
+        vip.cpu.ie = true;
+        vip.cycle = FIRST_DISPLAY_LINE * CYCLES_PER_LINE;
+        assert!(!vip.interrupt_requested());
+    }
+
+    #[test]
+    fn dma_fetches_eight_bytes_for_each_display_line() {
+        // BR 10 at 0x10, with R3 as the program counter so R0 is left to DMA
+        let mut vip = running(&[]);
+        vip.ram[0x10] = 0x30;
+        vip.ram[0x11] = 0x10;
+        vip.cpu.p = 3;
+        vip.cpu.r[3] = 0x10;
+        vip.cpu.ie = false;
+        vip.display_on = true;
+        vip.cpu.r[0] = 0x100;
+        for (i, byte) in vip.ram[0x100..0x500].iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        vip.run_frame(0, &mut Hooks::default()).unwrap();
+        assert_eq!(vip.cpu.r[0], 0x500);
+        for (line, row) in vip.lines.iter().enumerate() {
+            let start = 0x100 + 8 * line;
+            assert_eq!(*row, u64::from_be_bytes(vip.ram[start..start + 8].try_into().unwrap()), "line {}", line);
+        }
+        assert_eq!(vip.video[1], (vip.lines[4] as u128) << 64);
+    }
+
+    #[test]
+    fn ef1_is_raised_for_four_lines_before_the_display_starts_and_ends() {
+        let mut vip = running(&[0x34, 0x20]);
+        for line in 0..LINES_PER_FRAME {
+            vip.cycle = line * CYCLES_PER_LINE;
+            assert_eq!(vip.frame_flag(), (76..80).contains(&line) || (204..208).contains(&line), "line {}", line);
+        }
+        vip.cycle = 77 * CYCLES_PER_LINE;
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x20);
+        vip.cpu.r[0] = 0;
+        vip.cycle = 80 * CYCLES_PER_LINE;
+        vip.execute();
+        assert_eq!(vip.cpu.r[0], 0x02);
+    }
+
+    #[test]
+    fn refuses_platforms_that_need_more_than_a_vip() {
+        let images = VipImages {
+            monitor: Vec::new(),
+            interpreter: Vec::new(),
+        };
+        assert!(Vip::new(&images, &[0], &PlatformName::Hires.platform()).is_ok());
+        for name in [PlatformName::Eti660, PlatformName::Chip8X, PlatformName::Schip, PlatformName::MegaChip] {
+            assert!(matches!(Vip::new(&images, &[0], &name.platform()), Err(RomError::NotForVip(refused)) if refused == name));
+        }
+    }
+}